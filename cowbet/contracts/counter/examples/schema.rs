@@ -1,10 +1,9 @@
 use std::env::current_dir;
 use std::fs::create_dir_all;
 
-use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
+use cosmwasm_schema::{export_schema, remove_schemas};
 
-use counter::msg::{CountResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
-use counter::state::State;
+use counter::schema::schemas;
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -12,9 +11,7 @@ fn main() {
     create_dir_all(&out_dir).unwrap();
     remove_schemas(&out_dir).unwrap();
 
-    export_schema(&schema_for!(InstantiateMsg), &out_dir);
-    export_schema(&schema_for!(ExecuteMsg), &out_dir);
-    export_schema(&schema_for!(QueryMsg), &out_dir);
-    export_schema(&schema_for!(State), &out_dir);
-    export_schema(&schema_for!(CountResponse), &out_dir);
+    for schema in schemas() {
+        export_schema(&schema, &out_dir);
+    }
 }
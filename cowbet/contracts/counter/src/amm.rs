@@ -0,0 +1,72 @@
+// Constant-product pricing for binary Amm vaults.
+//
+// The pool holds reserves of both outcome units; swaps keep their product
+// constant, rounding in the pool's favour.
+use cosmwasm_std::{Decimal, Uint128, Uint256};
+use std::convert::TryInto;
+
+/// swap_return returns how many units of the other outcome the pool pays for
+/// `amount` units of outcome `offer`
+pub fn swap_return(reserves: &[Uint128], offer: usize, amount: Uint128) -> Uint128 {
+    let ask = 1 - offer;
+    let offer_after = Uint256::from(reserves[offer].u128() + amount.u128());
+    let product = Uint256::from(reserves[offer].u128()) * Uint256::from(reserves[ask].u128());
+    // the pool keeps at least product / offer_after, rounded up
+    let mut ask_after = product / offer_after;
+    if ask_after * offer_after != product {
+        ask_after += Uint256::from(1u8);
+    }
+    let ask_after: Uint128 = ask_after.try_into().expect("amm swap overflow");
+    reserves[ask].saturating_sub(ask_after)
+}
+
+/// provision splits `amount` of new liquidity into the units added to each
+/// reserve, keeping the pool's prices unchanged; the rest goes back to the
+/// provider. Returns zero everywhere for an empty pool, which takes `amount`
+/// of each outcome as is.
+pub fn provision(reserves: &[Uint128], amount: Uint128) -> Vec<Uint128> {
+    let max = reserves.iter().max().copied().unwrap_or_default();
+    if max.is_zero() {
+        return vec![amount; reserves.len()];
+    }
+    reserves.iter().map(|r| amount.multiply_ratio(*r, max)).collect()
+}
+
+/// prices returns the implied probability of each outcome, which sum to one
+pub fn prices(reserves: &[Uint128]) -> Vec<Decimal> {
+    let total = reserves[0] + reserves[1];
+    if total.is_zero() {
+        return vec![Decimal::percent(50); 2];
+    }
+    vec![
+        Decimal::from_ratio(reserves[1], total),
+        Decimal::from_ratio(reserves[0], total),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn swap_keeps_product_constant() {
+        let reserves = vec![Uint128::new(1_000), Uint128::new(1_000)];
+        // 1000 * 1000 / 1100 = 909.09 -> pool keeps 910
+        assert_eq!(swap_return(&reserves, 0, Uint128::new(100)), Uint128::new(90));
+        assert_eq!(swap_return(&reserves, 1, Uint128::new(1_000)), Uint128::new(500));
+        assert_eq!(swap_return(&reserves, 1, Uint128::zero()), Uint128::zero());
+    }
+
+    #[test]
+    fn scarce_outcome_is_priced_higher() {
+        let reserves = vec![Uint128::new(250), Uint128::new(750)];
+        assert_eq!(prices(&reserves), vec![Decimal::percent(75), Decimal::percent(25)]);
+    }
+
+    #[test]
+    fn provision_keeps_reserve_ratio() {
+        let reserves = vec![Uint128::new(500), Uint128::new(2_000)];
+        assert_eq!(provision(&reserves, Uint128::new(100)), vec![Uint128::new(25), Uint128::new(100)]);
+        assert_eq!(provision(&[Uint128::zero(); 2], Uint128::new(100)), vec![Uint128::new(100); 2]);
+    }
+}
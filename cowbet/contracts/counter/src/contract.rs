@@ -2,23 +2,47 @@
 //use cosmwasm_std::entry_point;
 //use cosmwasm_std::{to_binary};
 use cosmwasm_std::{
-    Uint64, Uint128, attr, coin, entry_point, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, Response, StdError, StdResult, Storage,
+    Uint64, Uint128, Uint256, attr, coin, entry_point, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env,
+    Event, Fraction, MessageInfo, Order, QueryRequest, Reply, Response, StdError, StdResult, Storage, SubMsg, WasmMsg, WasmQuery,
 };
+use cosmwasm_storage::ReadonlyBucket;
 use cw2::set_contract_version;
+use cw4::Cw4Contract;
+use cw721::{Cw721QueryMsg, OwnerOfResponse};
+use cw721_base::msg::{ExecuteMsg as Cw721ExecuteMsg, InstantiateMsg as Cw721InstantiateMsg, MintMsg};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
 
 use crate::coin_helpers::{validate_sent_sufficient_coin};
 use crate::error::ContractError;
-use crate::msg::{CreateVaultResponse, ExecuteMsg, InstantiateMsg, VaultResponse, QueryMsg, TokenBetResponse,};
-use crate::state::{ bank, bank_read, config, config_read, vault, vault_read, Vault, VaultStatus, State, Voter,
+use crate::events::{event, parlay_status_name, status_name, vault_event, with_balance, with_status};
+use crate::{amm, lmsr};
+use crate::msg::{BettorStatsResponse, CreateVaultMsg, CashOutQuoteResponse, CreateSeriesResponse, CreateTemplateMsg, CreateTemplateResponse, CreateVaultResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, OutcomePrice, LpSharesResponse, LeaderboardResponse, OutcomeShares, ParlayLegMsg, ParlayResponse, PlaceParlayResponse, PoolResponse, PositionResponse, PositionsResponse, ReferralResponse, PricesResponse, ReceiptResponse, SharesResponse, SimulateSwapResponse, SimulateTradeResponse, VaultListResponse, VaultMemberResponse, VaultResponse, QueryMsg, TokenBetResponse,};
+use crate::state::{ amm_pool, amm_pool_read, bank, bank_read, bettor_stats, bettor_stats_read, category_index, category_index_read, creator_stats, creator_stats_read, leaderboard, leaderboard_read, commitments, commitments_read, config, config_read, lp_shares, lp_shares_read, lmsr_market, lmsr_market_read, parlay, parlay_read, receipts, referral_earnings, referral_earnings_read, referrer, referrer_read, receipts_read, series, series_read, shares, shares_read, tag_index, tag_index_read, template, template_read, vault, vault_members, vault_members_read, vault_read, AmmPool, BettorStats, Commitment, CreatorStats, LeaderboardMetric, DisputeStatus, LmsrMarket, Parlay, ParlayLeg, ParlayStatus, Vault, VaultKind, VaultMetadata, VaultSeries, VaultStatus, VaultTemplate, State, TokenManager, Voter,
 };
 
 
 pub const DEFAULT_END_HEIGHT_BLOCKS: &u64 = &100_800_u64;
+pub const DEFAULT_RESOLUTION_PERIOD_BLOCKS: u64 = 100_800;
 
 const MIN_BET_AMOUNT: u128 = 1;
 const MIN_DESC_LENGTH: u64 = 3;
 const MAX_DESC_LENGTH: u64 = 64;
+const MAX_OUTCOMES: u64 = 16;
+const MAX_TAGS: u64 = 8;
+const MAX_LABEL_LENGTH: u64 = 32;
+const MAX_URI_LENGTH: u64 = 256;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+const LEADERBOARD_METRICS: [LeaderboardMetric; 4] = [
+    LeaderboardMetric::TotalWagered,
+    LeaderboardMetric::TotalWon,
+    LeaderboardMetric::Wins,
+    LeaderboardMetric::NetPnl,
+];
+const MAX_PARLAY_LEGS: u64 = 8;
+const MAX_LEG_ODDS: u64 = 100;
+const INSTANTIATE_POSITION_NFT_REPLY_ID: u64 = 1;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cowbet-v2";
@@ -31,15 +55,53 @@ pub fn instantiate(
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    let treasury = match msg.treasury {
+        Some(treasury) => deps.api.addr_validate(&treasury)?,
+        None => info.sender.clone(),
+    };
     let state = State {
         denom: msg.denom,
         owner: info.sender.clone(),
         vault_count: 0,
         bet_tokens: Uint128::zero(),
+        position_nft: None,
+        parlay_count: 0,
+        parlay_reserve: Uint128::zero(),
+        template_count: 0,
+        series_count: 0,
+        creation_bond: msg.creation_bond.unwrap_or_default(),
+        resolution_period: msg.resolution_period.unwrap_or(DEFAULT_RESOLUTION_PERIOD_BLOCKS),
+        dispute_period: 0,
+        treasury,
+        protocol_fee: Decimal::zero(),
+        referral_share: Decimal::zero(),
     };
     config(deps.storage).save(&state)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    Ok(Response::default())
+    // position receipts live in a companion cw721 contract this one mints from
+    let mut res = Response::new().add_event(
+        event("instantiate")
+            .add_attribute("owner", &state.owner)
+            .add_attribute("denom", &state.denom)
+            .add_attribute("treasury", &state.treasury),
+    );
+    if let Some(code_id) = msg.position_nft_code_id {
+        let nft_msg = Cw721InstantiateMsg {
+            name: "CowBet positions".to_string(),
+            symbol: "COWBET".to_string(),
+            minter: _env.contract.address.to_string(),
+        };
+        let instantiate = WasmMsg::Instantiate {
+            admin: None,
+            code_id,
+            msg: to_binary(&nft_msg)?,
+            funds: vec![],
+            label: "cowbet positions".to_string(),
+        };
+        res = res.add_submessage(SubMsg::reply_on_success(instantiate, INSTANTIATE_POSITION_NFT_REPLY_ID));
+    }
+    Ok(res)
     /*
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     STATE.save(deps.storage, &state)?;
@@ -50,6 +112,42 @@ pub fn instantiate(
         .add_attribute("count", msg.count.to_string()))*/
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_event(event("migrate").add_attribute("version", CONTRACT_VERSION)))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    if msg.id != INSTANTIATE_POSITION_NFT_REPLY_ID {
+        return Err(ContractError::Std(StdError::generic_err("unknown reply id")));
+    }
+    let res = msg.result.into_result().map_err(StdError::generic_err)?;
+    let address = res
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "_contract_address")
+        .map(|attr| attr.value.clone());
+    let address = match address {
+        Some(address) => deps.api.addr_validate(&address)?,
+        None => return Err(ContractError::Std(StdError::generic_err("no position nft address in reply"))),
+    };
+
+    let mut state = config_read(deps.storage).load()?;
+    state.position_nft = Some(address.clone());
+    config(deps.storage).save(&state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "position_nft_instantiated")
+        .add_attribute("position_nft", &address)
+        .add_event(event("position_nft_instantiated").add_attribute("position_nft", address)))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -58,7 +156,7 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::BetTokens {} => bet_tokens(deps, _env, info),
+        ExecuteMsg::BetTokens { referrer } => bet_tokens(deps, _env, info, referrer),
         ExecuteMsg::WithdrawRewards { amount } => {
             withdraw_bet_rewards(deps, _env, info, amount)
         }
@@ -66,31 +164,148 @@ pub fn execute(
             vault_id,
             vote,
             weight,
-        } => cast_bet(deps, _env, info, vault_id, vote, weight),
+            referrer,
+        } => cast_bet(deps, _env, info, vault_id, vote, weight, referrer),
         ExecuteMsg::EndDeposits { vault_id } => end_deposits(deps, _env, info, vault_id),
         ExecuteMsg::EndVault { vault_id } => end_vault(deps, _env, info, vault_id),
-        ExecuteMsg::CreateVault {
-            description,
-            start_height,
-            end_height,
-        } => create_vault(
+        ExecuteMsg::CreateVault(msg) => create_vault(deps, _env, info, msg),
+        ExecuteMsg::RetractBet { vault_id, amount } => {
+            retract_bet(deps, _env, info, vault_id, amount)
+        }
+        ExecuteMsg::FundCashOutReserve { vault_id, amount } => {
+            fund_cash_out_reserve(deps, info, vault_id, amount)
+        }
+        ExecuteMsg::CashOut { vault_id } => cash_out(deps, info, vault_id),
+        ExecuteMsg::TransferPosition {
+            vault_id,
+            recipient,
+            amount,
+        } => transfer_position(deps, info, vault_id, recipient, amount),
+        ExecuteMsg::MintReceipt { vault_id } => mint_receipt(deps, info, vault_id),
+        ExecuteMsg::ClaimReceipt { token_id } => claim_receipt(deps, info, token_id),
+        ExecuteMsg::AddMembers { vault_id, addresses } => {
+            update_members(deps, info, vault_id, addresses, true)
+        }
+        ExecuteMsg::RemoveMembers { vault_id, addresses } => {
+            update_members(deps, info, vault_id, addresses, false)
+        }
+        ExecuteMsg::CommitBet {
+            vault_id,
+            commitment,
+            weight,
+        } => commit_bet(deps, info, vault_id, commitment, weight),
+        ExecuteMsg::RevealBet {
+            vault_id,
+            vote,
+            salt,
+        } => reveal_bet(deps, _env, info, vault_id, vote, salt),
+        ExecuteMsg::ExpireCommitments { vault_id } => expire_commitments(deps, _env, vault_id),
+        ExecuteMsg::BuyShares {
+            vault_id,
+            outcome,
+            shares,
+            max_cost,
+        } => trade_shares(deps, _env, info, vault_id, outcome, shares, max_cost, false),
+        ExecuteMsg::SellShares {
+            vault_id,
+            outcome,
+            shares,
+            min_proceeds,
+        } => trade_shares(deps, _env, info, vault_id, outcome, shares, min_proceeds, true),
+        ExecuteMsg::SplitCollateral { vault_id, amount } => {
+            split_collateral(deps, _env, info, vault_id, amount)
+        }
+        ExecuteMsg::MergeOutcomes { vault_id, amount } => {
+            merge_outcomes(deps, info, vault_id, amount)
+        }
+        ExecuteMsg::SwapOutcome {
+            vault_id,
+            offer_outcome,
+            amount,
+            min_return,
+        } => swap_outcome(deps, _env, info, vault_id, offer_outcome, amount, min_return),
+        ExecuteMsg::ProvideLiquidity { vault_id, amount } => {
+            provide_liquidity(deps, _env, info, vault_id, amount)
+        }
+        ExecuteMsg::WithdrawLiquidity { vault_id, shares } => {
+            withdraw_liquidity(deps, info, vault_id, shares)
+        }
+        ExecuteMsg::ResolveVault { vault_id, outcome } => {
+            resolve_vault(deps, _env, info, vault_id, outcome)
+        }
+        ExecuteMsg::ResolveWithValue { vault_id, value } => {
+            resolve_with_value(deps, _env, info, vault_id, value)
+        }
+        ExecuteMsg::ClaimWinnings { vault_id } => claim_winnings(deps, info, vault_id),
+        ExecuteMsg::ExpireVault { vault_id } => expire_vault(deps, _env, vault_id),
+        ExecuteMsg::DisputeResult { vault_id } => dispute_result(deps, _env, info, vault_id),
+        ExecuteMsg::SettleDispute {
+            vault_id,
+            outcome,
+            value,
+        } => settle_dispute(deps, _env, info, vault_id, outcome, value),
+        ExecuteMsg::FinalizeVault { vault_id } => finalize_vault(deps, _env, vault_id),
+        ExecuteMsg::UpdateConfig {
+            creation_bond,
+            resolution_period,
+            dispute_period,
+            treasury,
+            protocol_fee,
+            referral_share,
+        } => update_config(
             deps,
-            _env,
             info,
-            description,
-            start_height,
-            end_height,
+            creation_bond,
+            resolution_period,
+            dispute_period,
+            treasury,
+            protocol_fee,
+            referral_share,
         ),
+        ExecuteMsg::FundParlayReserve { amount } => fund_parlay_reserve(deps, info, amount),
+        ExecuteMsg::WithdrawParlayReserve { amount } => withdraw_parlay_reserve(deps, info, amount),
+        ExecuteMsg::PlaceParlay {
+            legs,
+            stake,
+            min_payout,
+        } => place_parlay(deps, _env, info, legs, stake, min_payout),
+        ExecuteMsg::SettleParlay { parlay_id } => settle_parlay(deps, parlay_id),
+        ExecuteMsg::CreateTemplate(msg) => create_template(deps, info, msg),
+        ExecuteMsg::CreateSeries {
+            template_id,
+            interval,
+            count,
+        } => create_series(deps, _env, info, template_id, interval, count),
+        ExecuteMsg::SpawnSeriesVault { series_id } => spawn_next_vault(deps, _env, series_id),
         /*ExecuteMsg::Increment {} => try_increment(deps),
         ExecuteMsg::Reset { count } => try_reset(deps, info, count),*/
     }
 }
 
+/// set_referrer stores who referred a new user, one the bank has no entry
+/// for yet. A referrer named by anyone who has used the contract before is
+/// ignored, so an existing user cannot pick one to kick back fees to.
+fn set_referrer(deps: DepsMut, user: &Addr, referred_by: Option<String>) -> Result<(), ContractError> {
+    let referrer_addr = match referred_by {
+        Some(address) => deps.api.addr_validate(&address)?,
+        None => return Ok(()),
+    };
+    if &referrer_addr == user {
+        return Err(ContractError::InvalidReferrer {});
+    }
+    if bank_read(deps.storage).may_load(user.as_bytes())?.is_none() {
+        referrer(deps.storage).save(user.as_bytes(), &referrer_addr)?;
+    }
+    Ok(())
+}
+
 pub fn bet_tokens(
-    deps: DepsMut,
+    mut deps: DepsMut,
     _env: Env,
     info: MessageInfo,
+    referrer: Option<String>,
 ) -> Result<Response, ContractError> {
+    set_referrer(deps.branch(), &info.sender, referrer)?;
     let key = info.sender.as_str().as_bytes();
     let mut token_manager = bank_read(deps.storage).may_load(key)?.unwrap_or_default();
     let mut state = config(deps.storage).load()?;
@@ -108,8 +323,12 @@ pub fn bet_tokens(
     config(deps.storage).save(&state)?;
 
     bank(deps.storage).save(key, &token_manager)?;
+    let balance = free_balance(deps.storage, &info.sender)?;
 
-    Ok(Response::default())
+    Ok(Response::new()
+        .add_attribute("action", "bet_tokens")
+        .add_attribute("amount", funds.amount)
+        .add_event(with_balance(event("deposit").add_attribute("amount", funds.amount), &info.sender, balance)))
 }
 
 pub fn withdraw_bet_rewards(
@@ -123,10 +342,10 @@ pub fn withdraw_bet_rewards(
         Must add the farmed rewards + BET PROFIT
     */
     if let Some(mut token_manager) = bank_read(deps.storage).may_load(sender_address_raw)? {
-        let largest_bet = bet_amount(&sender_address_raw, deps.storage);
+        let locked = bet_amount(&sender_address_raw, deps.storage);
         let withdraw_amount = amount.unwrap_or(token_manager.token_balance);
-        if largest_bet + withdraw_amount > token_manager.token_balance {
-            let max_amount = token_manager.token_balance.checked_sub(largest_bet)?;
+        if locked + withdraw_amount > token_manager.token_balance {
+            let max_amount = token_manager.token_balance.checked_sub(locked)?;
             Err(ContractError::ExcessiveWithdraw { max_amount })
         } else {
             let balance = token_manager.token_balance.checked_sub(withdraw_amount)?;
@@ -138,11 +357,13 @@ pub fn withdraw_bet_rewards(
             state.bet_tokens = bet_tokens;
             config(deps.storage).save(&state)?;
 
+            let withdrawn = event("withdraw").add_attribute("amount", withdraw_amount);
             Ok(send_tokens (
                 &info.sender,
                 vec![coin(withdraw_amount.u128(), &state.denom)],
                 "approve",
-            ))
+            )
+            .add_event(with_balance(withdrawn, &info.sender, balance.checked_sub(locked)?)))
         }
     } else {
         Err(ContractError::VaultNoBet {})
@@ -164,6 +385,97 @@ fn validate_description(description: &str) -> Result<(), ContractError> {
         Ok(())
     }
 }
+/// validate_metadata returns an error if a category or tag is empty or too
+/// long, tags repeat, a URI is too long or the rules hash is not a sha256
+fn validate_metadata(metadata: &VaultMetadata) -> Result<(), ContractError> {
+    let labels = metadata.category.iter().chain(metadata.tags.iter());
+    let bad_label = labels.clone().any(|l| l.is_empty() || l.len() as u64 > MAX_LABEL_LENGTH);
+    let mut tags = metadata.tags.clone();
+    tags.sort();
+    tags.dedup();
+    let bad_tags = metadata.tags.len() as u64 > MAX_TAGS || tags.len() != metadata.tags.len();
+    let bad_uri = metadata
+        .resolution_source
+        .iter()
+        .chain(metadata.image_uri.iter())
+        .any(|uri| uri.len() as u64 > MAX_URI_LENGTH);
+    let bad_hash = metadata.rules_hash.as_ref().is_some_and(|hash| hash.len() != 32);
+    if bad_label || bad_tags || bad_uri || bad_hash {
+        return Err(ContractError::InvalidMetadata {
+            max_tags: MAX_TAGS,
+            max_label_length: MAX_LABEL_LENGTH,
+            max_uri_length: MAX_URI_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+/// validate_bet_limits returns an error if the vault's bet limits contradict each other
+fn validate_bet_limits(
+    min_bet: Uint128,
+    max_bet: Option<Uint128>,
+    max_pool: Option<Uint128>,
+) -> Result<(), ContractError> {
+    let too_low = min_bet.u128() < MIN_BET_AMOUNT;
+    let above_max_bet = max_bet.is_some_and(|max| min_bet > max);
+    let above_max_pool = max_pool.is_some_and(|max| min_bet > max);
+    if too_low || above_max_bet || above_max_pool {
+        Err(ContractError::InvalidBetLimits {})
+    } else {
+        Ok(())
+    }
+}
+
+/// validate_outcomes returns an error unless there are 2 to MAX_OUTCOMES
+/// distinct, non-empty outcome names
+fn validate_outcomes(outcomes: &[String]) -> Result<(), ContractError> {
+    let mut names: Vec<&String> = outcomes.iter().collect();
+    names.sort();
+    names.dedup();
+    let too_few = outcomes.len() < 2 || (outcomes.len() as u64) > MAX_OUTCOMES;
+    if too_few || names.len() != outcomes.len() || outcomes.iter().any(|o| o.is_empty()) {
+        Err(ContractError::InvalidOutcomes { max_outcomes: MAX_OUTCOMES })
+    } else {
+        Ok(())
+    }
+}
+
+/// bucket_outcomes returns the outcome names of the ranges between
+/// consecutive boundaries, or an error unless they strictly ascend and give
+/// 2 to MAX_OUTCOMES ranges
+fn bucket_outcomes(buckets: &[Decimal]) -> Result<Vec<String>, ContractError> {
+    let count = buckets.len() as u64;
+    if !(3..=MAX_OUTCOMES + 1).contains(&count) || buckets.windows(2).any(|w| w[0] >= w[1]) {
+        return Err(ContractError::InvalidBuckets { max_boundaries: MAX_OUTCOMES + 1 });
+    }
+    Ok(buckets.windows(2).map(|w| format!("{}-{}", w[0], w[1])).collect())
+}
+
+/// bucket_index returns the range holding `value`. Ranges include their lower
+/// boundary; values outside all of them fall in the first or last range.
+fn bucket_index(buckets: &[Decimal], value: Decimal) -> usize {
+    let above = buckets[1..buckets.len() - 1].iter().filter(|b| value >= **b).count();
+    above.min(buckets.len() - 2)
+}
+
+/// outcome_index returns the position of an outcome in the vault, or an
+/// error if the vault has no such outcome
+fn outcome_index(a_vault: &Vault, outcome: &str) -> Result<usize, ContractError> {
+    match a_vault.outcomes.iter().position(|o| o == outcome) {
+        Some(index) => Ok(index),
+        None => Err(ContractError::InvalidBetEvent {}),
+    }
+}
+
+/// free_balance returns the part of the address' balance not locked in bets
+fn free_balance(storage: &dyn Storage, address: &Addr) -> StdResult<Uint128> {
+    let token_manager = bank_read(storage)
+        .may_load(address.as_bytes())?
+        .unwrap_or_default();
+    let locked: Uint128 = token_manager.bet_token.iter().map(|(_, v)| *v).sum();
+    Ok(token_manager.token_balance.saturating_sub(locked))
+}
+
 /// validate_end_height returns an error if the poll ends in the past
 fn validate_end_height(end_height: Option<u64>, env: Env) -> Result<(), ContractError> {
     if end_height.is_some() && env.block.height >= end_height.unwrap() {
@@ -178,21 +490,170 @@ pub fn create_vault(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    description: String,
-    start_height: Option<u64>,
-    end_height: Option<u64>,
+    msg: CreateVaultMsg,
 ) -> Result<Response, ContractError> {
+    let CreateVaultMsg {
+        description,
+        kind,
+        outcomes,
+        start_height,
+        end_height,
+        allow_side_switch,
+        early_exit_fee,
+        min_bet,
+        max_bet,
+        max_pool,
+        allowlist,
+        member_group,
+        commit_reveal,
+        swap_fee,
+        cash_out,
+        buckets,
+        condition,
+        metadata,
+    } = msg;
     validate_end_height(end_height, env.clone())?;
     validate_description(&description)?;
+    if let Some(metadata) = &metadata {
+        validate_metadata(metadata)?;
+    }
+    let min_bet = min_bet.unwrap_or_else(|| Uint128::from(MIN_BET_AMOUNT));
+    validate_bet_limits(min_bet, max_bet, max_pool)?;
+    let member_group = match member_group {
+        Some(group) => Some(deps.api.addr_validate(&group)?),
+        None => None,
+    };
+    let private = allowlist.is_some() || member_group.is_some();
+    let early_exit_fee = early_exit_fee.unwrap_or_else(Decimal::zero);
+    if early_exit_fee > Decimal::one() {
+        return Err(ContractError::InvalidExitFee {});
+    }
+    let kind = kind.unwrap_or(VaultKind::Parimutuel);
+    let outcomes = match kind {
+        VaultKind::Scalar { .. } => {
+            let sides = vec!["long".to_string(), "short".to_string()];
+            if outcomes.is_some_and(|outcomes| outcomes != sides) {
+                return Err(ContractError::InvalidOutcomes { max_outcomes: 2 });
+            }
+            sides
+        }
+        _ => match &buckets {
+            Some(buckets) => {
+                if outcomes.is_some() {
+                    return Err(ContractError::InvalidOutcomes { max_outcomes: MAX_OUTCOMES });
+                }
+                bucket_outcomes(buckets)?
+            }
+            None => outcomes.unwrap_or_else(|| vec!["yes".to_string(), "no".to_string()]),
+        },
+    };
+    if buckets.is_some() && matches!(kind, VaultKind::Scalar { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    validate_outcomes(&outcomes)?;
+    match kind {
+        VaultKind::Lmsr { liquidity } | VaultKind::Amm { liquidity } => {
+            if liquidity.is_zero() {
+                return Err(ContractError::InvalidLiquidity {});
+            }
+            if commit_reveal.is_some() {
+                return Err(ContractError::VaultKindMismatch {});
+            }
+        }
+        VaultKind::Scalar { lower, upper } => {
+            if lower >= upper {
+                return Err(ContractError::InvalidScalarBounds {});
+            }
+        }
+        VaultKind::Parimutuel => {}
+    }
+    if matches!(kind, VaultKind::Amm { .. }) && outcomes.len() != 2 {
+        return Err(ContractError::InvalidOutcomes { max_outcomes: 2 });
+    }
+    let swap_fee = swap_fee.unwrap_or_else(Decimal::zero);
+    if swap_fee >= Decimal::one() {
+        return Err(ContractError::InvalidSwapFee {});
+    }
+    if !swap_fee.is_zero() && !matches!(kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if let Some(cash_out) = &cash_out {
+        if cash_out.discount > Decimal::one() {
+            return Err(ContractError::InvalidCashOutDiscount {});
+        }
+        if !kind.is_pooled() {
+            return Err(ContractError::VaultKindMismatch {});
+        }
+    }
+
+    let mut parent = None;
+    if let Some(condition) = &condition {
+        if !kind.is_pooled() {
+            return Err(ContractError::VaultKindMismatch {});
+        }
+        let parent_key = condition.vault_id.to_be_bytes();
+        let a_parent = match vault_read(deps.storage).may_load(&parent_key)? {
+            Some(a_parent) => a_parent,
+            None => return Err(ContractError::VaultDoesNotExist {}),
+        };
+        let unresolved = matches!(a_parent.status, VaultStatus::DepositsOpen | VaultStatus::InProgress);
+        let by_outcome = !matches!(a_parent.kind, VaultKind::Scalar { .. });
+        if !unresolved || !by_outcome || !a_parent.outcomes.contains(&condition.outcome) {
+            return Err(ContractError::InvalidCondition {});
+        }
+        parent = Some(a_parent);
+    }
 
     let mut state = config(deps.storage).load()?;
     let vault_count = state.vault_count;
     let vault_id = state.vault_count + 1;
     state.vault_count = vault_id;
 
+    // An Lmsr market maker can lose at most b * ln(n), which the creator funds
+    // up front; an Amm pool is seeded with the creator's collateral.
+    let funding = match kind {
+        VaultKind::Lmsr { liquidity } => {
+            lmsr::cost(liquidity, &vec![Uint128::zero(); outcomes.len()], true)?
+        }
+        VaultKind::Amm { liquidity } => liquidity,
+        VaultKind::Parimutuel | VaultKind::Scalar { .. } => Uint128::zero(),
+    };
+    // the creation bond is held until the vault resolves
+    let bond = state.creation_bond;
+    if !(funding + bond).is_zero() {
+        if free_balance(deps.storage, &info.sender)? < funding + bond {
+            return Err(ContractError::VaultInsufficientAmt {});
+        }
+        let creator_key = info.sender.as_bytes();
+        let mut token_manager = bank_read(deps.storage).load(creator_key)?;
+        token_manager.token_balance = token_manager.token_balance.checked_sub(funding + bond)?;
+        bank(deps.storage).save(creator_key, &token_manager)?;
+    }
+    match kind {
+        VaultKind::Lmsr { .. } => {
+            let shares = vec![Uint128::zero(); outcomes.len()];
+            let market = LmsrMarket { shares, collateral: funding };
+            lmsr_market(deps.storage).save(&vault_id.to_be_bytes(), &market)?;
+        }
+        // the creator is the pool's first liquidity provider
+        VaultKind::Amm { .. } => {
+            let pool = AmmPool {
+                reserves: vec![funding; 2],
+                collateral: funding,
+                swap_fee,
+                total_lp_shares: funding,
+            };
+            amm_pool(deps.storage).save(&vault_id.to_be_bytes(), &pool)?;
+            lp_shares(deps.storage, vault_id).save(info.sender.as_bytes(), &funding)?;
+        }
+        VaultKind::Parimutuel | VaultKind::Scalar { .. } => {}
+    }
+
     let new_vault = Vault {
         creator: info.sender,
         status: VaultStatus::DepositsOpen, // Default to the 'Deposit' phase on creation.
+        kind,
+        outcomes,
         yes_votes: Uint128::zero(),
         no_votes: Uint128::zero(),
         sum_votes: Uint128::zero(),
@@ -201,21 +662,75 @@ pub fn create_vault(
         farmRewards: Uint128::zero(),
         end_height: end_height.unwrap_or(env.block.height + DEFAULT_END_HEIGHT_BLOCKS),
         start_height,
-        description: String::new(),
+        description,
         result: String::new(),
+        allow_side_switch: allow_side_switch.unwrap_or(false),
+        early_exit_fee,
+        exit_fees: Uint128::zero(),
+        min_bet,
+        max_bet,
+        max_pool,
+        private,
+        member_group,
+        commit_reveal,
+        committed_votes: Uint128::zero(),
+        reveal_deadline: None,
+        forfeited: Uint128::zero(),
+        cash_out,
+        cash_out_reserve: Uint128::zero(),
+        reserve_positions: vec![],
+        resolved_value: None,
+        buckets,
+        condition,
+        conditional_vaults: vec![],
+        series_id: None,
+        metadata,
+        bond,
+        resolution_period: state.resolution_period,
+        resolved_height: None,
+        dispute_period: state.dispute_period,
+        dispute: None,
+        final_height: None,
     };
     let key = state.vault_count.to_be_bytes();
     //vault(deps.storage).save(&state)?;
     vault(deps.storage).save(&key, &new_vault)?;
+    if let (Some(mut a_parent), Some(condition)) = (parent, &new_vault.condition) {
+        a_parent.conditional_vaults.push(vault_id);
+        vault(deps.storage).save(&condition.vault_id.to_be_bytes(), &a_parent)?;
+    }
+    if let Some(metadata) = &new_vault.metadata {
+        if let Some(category) = &metadata.category {
+            category_index(deps.storage, category).save(&key, &true)?;
+        }
+        for tag in metadata.tags.iter() {
+            tag_index(deps.storage, tag).save(&key, &true)?;
+        }
+    }
+    for address in allowlist.unwrap_or_default() {
+        let member = deps.api.addr_validate(&address)?;
+        vault_members(deps.storage, vault_id).save(member.as_bytes(), &true)?;
+    }
 
     config(deps.storage).save(&state)?;
+    record_creator(deps.storage, &new_vault.creator, |stats| stats.vaults_created += 1)?;
 
+    let mut created = vault_event("create_vault", vault_id)
+        .add_attribute("creator", &new_vault.creator)
+        .add_attribute("end_height", new_vault.end_height.to_string())
+        .add_attribute("status", status_name(&new_vault.status))
+        .add_attribute("bond", new_vault.bond);
+    if let Some(start_height) = new_vault.start_height {
+        created = created.add_attribute("start_height", start_height.to_string());
+    }
+    let created = with_balance(created, &new_vault.creator, free_balance(deps.storage, &new_vault.creator)?);
     let r = Response::new()
         .add_attribute("action", "create_vault")
-        .add_attribute("creator", new_vault.creator)
-        .add_attribute("vault_id", "&vault_id")
-        .add_attribute("end_height", "new_vault.end_height")
-        .add_attribute("start_height", "new_vault.start_height")
+        .add_attribute("creator", &new_vault.creator)
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("end_height", new_vault.end_height.to_string())
+        .add_attribute("start_height", new_vault.start_height.unwrap_or_default().to_string())
+        .add_event(created)
         //.add_data(Some(to_binary(&CreateVaultResponse { vault_id })?));
         .set_data(to_binary(&CreateVaultResponse { vault_id })?);
 
@@ -245,6 +760,186 @@ pub fn create_vault(
     Ok(r)*/
 }
 
+/// create_template stores vault settings the sender can spawn series from.
+/// Only pooled kinds qualify, since spawning never funds a market maker; the
+/// other settings are checked when a series spawns its first vault.
+pub fn create_template(
+    deps: DepsMut,
+    info: MessageInfo,
+    msg: CreateTemplateMsg,
+) -> Result<Response, ContractError> {
+    let kind = msg.kind.unwrap_or(VaultKind::Parimutuel);
+    if !kind.is_pooled() {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if let Some(metadata) = &msg.metadata {
+        validate_metadata(metadata)?;
+    }
+
+    let mut state = config(deps.storage).load()?;
+    state.template_count += 1;
+    let template_id = state.template_count;
+    config(deps.storage).save(&state)?;
+    let new_template = VaultTemplate {
+        creator: info.sender,
+        description: msg.description,
+        kind,
+        outcomes: msg.outcomes,
+        buckets: msg.buckets,
+        duration: msg.duration.unwrap_or(*DEFAULT_END_HEIGHT_BLOCKS),
+        allow_side_switch: msg.allow_side_switch.unwrap_or(false),
+        early_exit_fee: msg.early_exit_fee.unwrap_or_else(Decimal::zero),
+        min_bet: msg.min_bet,
+        max_bet: msg.max_bet,
+        max_pool: msg.max_pool,
+        metadata: msg.metadata,
+    };
+    template(deps.storage).save(&template_id.to_be_bytes(), &new_template)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_template")
+        .add_attribute("template_id", template_id.to_string())
+        .add_attribute("creator", &new_template.creator)
+        .add_event(
+            event("create_template")
+                .add_attribute("template_id", template_id.to_string())
+                .add_attribute("creator", new_template.creator),
+        )
+        .set_data(to_binary(&CreateTemplateResponse { template_id })?))
+}
+
+/// create_series lets a template's creator schedule `count` vaults whose
+/// deposit deadlines are `interval` blocks apart, spawning the first one
+pub fn create_series(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    template_id: u64,
+    interval: u64,
+    count: u64,
+) -> Result<Response, ContractError> {
+    let a_template = match template_read(deps.storage).may_load(&template_id.to_be_bytes())? {
+        Some(a_template) => a_template,
+        None => return Err(ContractError::TemplateDoesNotExist {}),
+    };
+    if a_template.creator != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if interval == 0 || count == 0 {
+        return Err(ContractError::InvalidSeries {});
+    }
+
+    let mut state = config(deps.storage).load()?;
+    state.series_count += 1;
+    let series_id = state.series_count;
+    config(deps.storage).save(&state)?;
+    let mut a_series = VaultSeries {
+        template_id,
+        interval,
+        count,
+        vault_ids: vec![],
+    };
+    let end_height = env.block.height + a_template.duration;
+    let (vault_id, created) = spawn_series_vault(deps, env, series_id, &mut a_series, end_height)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_series")
+        .add_attribute("series_id", series_id.to_string())
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_event(
+            event("create_series")
+                .add_attribute("series_id", series_id.to_string())
+                .add_attribute("template_id", template_id.to_string())
+                .add_attribute("interval", interval.to_string())
+                .add_attribute("count", count.to_string()),
+        )
+        .add_events(created)
+        .set_data(to_binary(&CreateSeriesResponse { series_id })?))
+}
+
+/// spawn_next_vault spawns the next vault of a series once the last one's
+/// deposits have ended. Anyone may call it; a deadline already behind when
+/// the vault is spawned moves on to the next one on the schedule.
+pub fn spawn_next_vault(deps: DepsMut, env: Env, series_id: u64) -> Result<Response, ContractError> {
+    let mut a_series = match series_read(deps.storage).may_load(&series_id.to_be_bytes())? {
+        Some(a_series) => a_series,
+        None => return Err(ContractError::SeriesDoesNotExist {}),
+    };
+    if a_series.vault_ids.len() as u64 >= a_series.count {
+        return Err(ContractError::SeriesComplete {});
+    }
+    let last_id = a_series.vault_ids.last().copied().unwrap_or_default();
+    let last_end = vault_read(deps.storage).load(&last_id.to_be_bytes())?.end_height;
+    if env.block.height < last_end {
+        return Err(ContractError::SeriesVaultNotDue { spawn_height: last_end });
+    }
+
+    let missed = (env.block.height - last_end) / a_series.interval;
+    let end_height = last_end + (missed + 1) * a_series.interval;
+    let (vault_id, created) = spawn_series_vault(deps, env, series_id, &mut a_series, end_height)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "spawn_series_vault")
+        .add_attribute("series_id", series_id.to_string())
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_event(
+            vault_event("spawn_series_vault", vault_id)
+                .add_attribute("series_id", series_id.to_string())
+                .add_attribute("index", a_series.vault_ids.len().to_string()),
+        )
+        .add_events(created)
+        .set_data(to_binary(&CreateVaultResponse { vault_id })?))
+}
+
+/// spawn_series_vault creates the next vault of a series from its template,
+/// in the template creator's name. It takes deposits for the template's
+/// duration up to `end_height`. Returns the vault id with the events of its
+/// creation.
+fn spawn_series_vault(
+    mut deps: DepsMut,
+    env: Env,
+    series_id: u64,
+    a_series: &mut VaultSeries,
+    end_height: u64,
+) -> Result<(u64, Vec<Event>), ContractError> {
+    let a_template = template_read(deps.storage).load(&a_series.template_id.to_be_bytes())?;
+    let n = a_series.vault_ids.len() + 1;
+    let start_height = end_height
+        .checked_sub(a_template.duration)
+        .filter(|start_height| *start_height > env.block.height);
+    let msg = CreateVaultMsg {
+        description: a_template.description.replace("{n}", &n.to_string()),
+        kind: Some(a_template.kind),
+        outcomes: a_template.outcomes,
+        start_height,
+        end_height: Some(end_height),
+        allow_side_switch: Some(a_template.allow_side_switch),
+        early_exit_fee: Some(a_template.early_exit_fee),
+        min_bet: a_template.min_bet,
+        max_bet: a_template.max_bet,
+        max_pool: a_template.max_pool,
+        allowlist: None,
+        member_group: None,
+        commit_reveal: None,
+        swap_fee: None,
+        cash_out: None,
+        buckets: a_template.buckets,
+        condition: None,
+        metadata: a_template.metadata,
+    };
+    let creator = MessageInfo { sender: a_template.creator, funds: vec![] };
+    let created = create_vault(deps.branch(), env, creator, msg)?;
+
+    let vault_id = config_read(deps.storage).load()?.vault_count;
+    let vault_key = vault_id.to_be_bytes();
+    let mut a_vault = vault_read(deps.storage).load(&vault_key)?;
+    a_vault.series_id = Some(series_id);
+    vault(deps.storage).save(&vault_key, &a_vault)?;
+    a_series.vault_ids.push(vault_id);
+    series(deps.storage).save(&series_id.to_be_bytes(), a_series)?;
+    Ok((vault_id, created.events))
+}
+
 pub fn end_deposits(
     deps: DepsMut,
     _env: Env,
@@ -274,30 +969,29 @@ pub fn end_deposits(
         });
     }
 
+    let previous = a_vault.status.clone();
     a_vault.status = VaultStatus::InProgress;
-
-    let mut no = 0u128;//0; //Uint128;
-    let mut yes = 0u128;//0; //Uint128;
-    let mut sum_votes = 0u128;
-
-    for voter in &a_vault.voter_info {
-        if voter.vote == "yes" {
-            yes += voter.weight.u128();
-        } else  if voter.vote == "no" {
-            no += voter.weight.u128();
-        }
+    // Committed bets can be revealed for a while once deposits have ended.
+    if let Some(commit_reveal) = &a_vault.commit_reveal {
+        a_vault.reveal_deadline = Some(_env.block.height + commit_reveal.reveal_period);
     }
+    vault(deps.storage).save(key, &a_vault)?;
 
-    sum_votes = yes + no;
-
+    let mut ended = with_status(vault_event("end_deposits", vault_id), &previous, &a_vault.status);
+    if let Some(reveal_deadline) = a_vault.reveal_deadline {
+        ended = ended.add_attribute("reveal_deadline", reveal_deadline.to_string());
+    }
     let r = Response::new()
         .add_attribute("action", "end_deposits")
-        .add_attribute("vault_id", "&vault_id");
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_event(ended);
     
     Ok(r)
 }
 
 
+/// end_vault lets the creator cancel a pooled vault while deposits are open.
+/// Every position can then be claimed back in full.
 pub fn end_vault(
     deps: DepsMut,
     env: Env,
@@ -322,15 +1016,198 @@ pub fn end_vault(
             return Err(ContractError::VaultDepositPeriodNotStarted { start_height });
         }
     }
+    if !a_vault.kind.is_pooled() {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    let previous = a_vault.status.clone();
+    cancel_vault(deps.storage, vault_id, &mut a_vault, false)?;
+    record_creator(deps.storage, &a_vault.creator, |stats| stats.cancelled += 1)?;
 
     let r = Response::new()
         .add_attribute("action", "end_vault")
-        .add_attribute("vault_id", "&vault_id");
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_event(with_balance(
+            with_status(vault_event("end_vault", vault_id), &previous, &a_vault.status),
+            &info.sender,
+            free_balance(deps.storage, &info.sender)?,
+        ));
     
     Ok(r)
 }
 
 
+/// metric_name returns the name a leaderboard is stored under
+fn metric_name(metric: LeaderboardMetric) -> &'static str {
+    match metric {
+        LeaderboardMetric::TotalWagered => "total_wagered",
+        LeaderboardMetric::TotalWon => "total_won",
+        LeaderboardMetric::Wins => "wins",
+        LeaderboardMetric::NetPnl => "net_pnl",
+    }
+}
+
+/// leaderboard_key returns the index key ranking an address by a metric.
+/// Net P&L is offset by 2^127 so that losses sort below profits.
+fn leaderboard_key(stats: &BettorStats, metric: LeaderboardMetric, address: &Addr) -> Vec<u8> {
+    let score = match metric {
+        LeaderboardMetric::TotalWagered => stats.total_wagered.u128(),
+        LeaderboardMetric::TotalWon => stats.total_won.u128(),
+        LeaderboardMetric::Wins => stats.wins as u128,
+        LeaderboardMetric::NetPnl => {
+            let (won, wagered) = (stats.total_won.u128(), stats.total_wagered.u128());
+            if won >= wagered {
+                (1 << 127) + (won - wagered)
+            } else {
+                (1 << 127) - (wagered - won)
+            }
+        }
+    };
+    let mut key = score.to_be_bytes().to_vec();
+    key.extend_from_slice(address.as_bytes());
+    key
+}
+
+/// record_bettor updates an address' lifetime stats, moving the address
+/// along every leaderboard
+fn record_bettor(storage: &mut dyn Storage, address: &Addr, update: impl FnOnce(&mut BettorStats)) -> StdResult<()> {
+    let old = bettor_stats_read(storage).may_load(address.as_bytes())?;
+    let mut stats = old.clone().unwrap_or_default();
+    update(&mut stats);
+    bettor_stats(storage).save(address.as_bytes(), &stats)?;
+    for metric in LEADERBOARD_METRICS {
+        if let Some(old) = &old {
+            leaderboard(storage, metric_name(metric)).remove(&leaderboard_key(old, metric, address));
+        }
+        leaderboard(storage, metric_name(metric)).save(&leaderboard_key(&stats, metric, address), &true)?;
+    }
+    Ok(())
+}
+
+/// record_settlement adds a settled position and what it paid to an address'
+/// stats. Its stake was counted when it was placed.
+fn record_settlement(storage: &mut dyn Storage, address: &Addr, payout: Uint128, win: bool) -> StdResult<()> {
+    record_bettor(storage, address, |stats| {
+        stats.total_won += payout;
+        if win {
+            stats.wins += 1;
+        }
+        stats.vaults_participated += 1;
+    })
+}
+
+/// record_creator updates the track record of a vault's creator
+fn record_creator(storage: &mut dyn Storage, creator: &Addr, update: impl FnOnce(&mut CreatorStats)) -> StdResult<()> {
+    let mut stats = creator_stats_read(storage).may_load(creator.as_bytes())?.unwrap_or_default();
+    update(&mut stats);
+    creator_stats(storage).save(creator.as_bytes(), &stats)
+}
+
+/// resolve_deadline returns the last block a vault's creator may resolve it
+/// in on time: the resolution period after deposits (or reveals) ended. A
+/// conditional vault cannot be resolved before its parent, so its period
+/// does not run out while the parent's result is not final and lasts at
+/// least the full resolution period after it is.
+fn resolve_deadline(storage: &dyn Storage, a_vault: &Vault) -> StdResult<u64> {
+    let settled_height = a_vault.reveal_deadline.unwrap_or_default().max(a_vault.end_height);
+    let deadline = settled_height + a_vault.resolution_period;
+    let condition = match &a_vault.condition {
+        Some(condition) => condition,
+        None => return Ok(deadline),
+    };
+    let parent = vault_read(storage).load(&condition.vault_id.to_be_bytes())?;
+    Ok(match parent.final_height {
+        Some(final_height) => deadline.max(final_height + a_vault.resolution_period),
+        None => u64::MAX,
+    })
+}
+
+/// cancel_vault closes a pooled vault so every position can be claimed back
+/// in full. Unrevealed commitments are unlocked, the creator gets back the
+/// cash out reserve and the stakes it bought along with any bond still held,
+/// and vaults conditional on this one are cancelled as well. Exit fees and
+/// forfeited commitments go to the creator too, unless the vault `expired`
+/// unresolved: then they go to the treasury.
+fn cancel_vault(storage: &mut dyn Storage, vault_id: u64, a_vault: &mut Vault, expired: bool) -> StdResult<()> {
+    let unrevealed = commitments_read(storage, vault_id)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (voter_key, commitment) in &unrevealed {
+        let mut token_manager = bank_read(storage).load(voter_key)?;
+        if !unlock_commitment(&mut token_manager, vault_id, commitment.weight)? {
+            token_manager.participated_vaults.retain(|k| k != &vault_id);
+        }
+        bank(storage).save(voter_key, &token_manager)?;
+        commitments(storage, vault_id).remove(voter_key);
+    }
+    a_vault.committed_votes = Uint128::zero();
+    a_vault.status = VaultStatus::Closed;
+
+    let bought: Uint128 = a_vault.reserve_positions.iter().map(|(_, weight)| *weight).sum();
+    let fees = a_vault.exit_fees + a_vault.forfeited;
+    let mut credits = vec![(a_vault.creator.clone(), a_vault.cash_out_reserve + bought + a_vault.bond)];
+    if expired {
+        credits.push((config_read(storage).load()?.treasury, fees));
+    } else {
+        credits[0].1 += fees;
+    }
+    a_vault.exit_fees = Uint128::zero();
+    a_vault.forfeited = Uint128::zero();
+    a_vault.bond = Uint128::zero();
+    vault(storage).save(&vault_id.to_be_bytes(), a_vault)?;
+    for (address, amount) in credits.into_iter().filter(|(_, amount)| !amount.is_zero()) {
+        let mut token_manager = bank_read(storage).may_load(address.as_bytes())?.unwrap_or_default();
+        token_manager.token_balance += amount;
+        bank(storage).save(address.as_bytes(), &token_manager)?;
+    }
+    cancel_conditional_vaults(storage, a_vault, None)
+}
+
+/// void_market closes an Lmsr or Amm vault without a result. The Lmsr market
+/// maker keeps what the outstanding shares redeem for and returns the rest of
+/// its collateral to the creator; an Amm pool's collateral backs its units
+/// exactly, so nothing is left over. Vaults conditional on it are cancelled.
+fn void_market(storage: &mut dyn Storage, vault_id: u64, a_vault: &mut Vault) -> StdResult<()> {
+    let vault_key = &vault_id.to_be_bytes();
+    a_vault.status = VaultStatus::Closed;
+    vault(storage).save(vault_key, a_vault)?;
+    if let VaultKind::Lmsr { .. } = a_vault.kind {
+        let mut market = lmsr_market_read(storage).load(vault_key)?;
+        let outstanding: Uint128 = market.shares.iter().copied().sum();
+        let n = a_vault.outcomes.len() as u128;
+        let owed = Uint128::new(outstanding.u128().div_ceil(n));
+        let residual = market.collateral.checked_sub(owed)?;
+        market.collateral = owed;
+        lmsr_market(storage).save(vault_key, &market)?;
+        let creator_key = a_vault.creator.as_bytes();
+        let mut token_manager = bank_read(storage).may_load(creator_key)?.unwrap_or_default();
+        token_manager.token_balance += residual;
+        bank(storage).save(creator_key, &token_manager)?;
+    }
+    cancel_conditional_vaults(storage, a_vault, None)
+}
+
+/// void_payout returns what outcome units of a voided Lmsr or Amm vault
+/// redeem for: one complete set of outcomes is worth one unit of collateral,
+/// so every unit is worth an equal share of it, rounded down
+fn void_payout(a_vault: &Vault, units: &[Uint128]) -> Uint128 {
+    let total: Uint128 = units.iter().copied().sum();
+    total.multiply_ratio(1u128, a_vault.outcomes.len() as u128)
+}
+
+/// cancel_conditional_vaults cancels the unresolved vaults depending on a
+/// vault, except those whose condition is the given outcome
+fn cancel_conditional_vaults(storage: &mut dyn Storage, a_vault: &Vault, outcome: Option<&str>) -> StdResult<()> {
+    for child_id in a_vault.conditional_vaults.iter() {
+        let mut child = vault_read(storage).load(&child_id.to_be_bytes())?;
+        let unresolved = matches!(child.status, VaultStatus::DepositsOpen | VaultStatus::InProgress);
+        let met = child.condition.as_ref().is_some_and(|c| Some(c.outcome.as_str()) == outcome);
+        if unresolved && !met {
+            cancel_vault(storage, *child_id, &mut child, false)?;
+        }
+    }
+    Ok(())
+}
+
 // At the end of the epoch, allow the address to withdraw their rewards.
 fn allow_claim(
     storage: &mut dyn Storage,
@@ -349,229 +1226,5693 @@ fn allow_claim(
     Ok(Response::default())
 }
 
+/// bet_amount returns the total weight the voter has locked across all vaults.
+/// cast_bet only lets a stake through if it fits next to all the others, so
+/// withdrawals have to keep the sum locked, not just the largest stake.
 fn bet_amount(voter: &[u8], storage: &dyn Storage) -> Uint128 {
     let token_manager = bank_read(storage).load(voter).unwrap();
     token_manager
         .bet_token
         .iter()
         .map(|(_, v)| *v)
-        .max()
-        .unwrap_or_default()
+        .sum()
+}
+
+/// tally_votes recomputes the per-side totals of a vault from its voters and
+/// the positions it bought back, along with each voter's share of the whole pool
+fn tally_votes(a_vault: &mut Vault) {
+    let mut yes = Uint128::zero();
+    let mut no = Uint128::zero();
+    let mut sum = Uint128::zero();
+    let positions = a_vault
+        .voter_info
+        .iter()
+        .map(|voter| (&voter.vote, voter.weight))
+        .chain(a_vault.reserve_positions.iter().map(|(vote, weight)| (vote, *weight)));
+    for (vote, weight) in positions {
+        if vote == "yes" {
+            yes += weight;
+        } else if vote == "no" {
+            no += weight;
+        }
+        sum += weight;
+    }
+    a_vault.yes_votes = yes;
+    a_vault.no_votes = no;
+    a_vault.sum_votes = sum;
+    let sum_votes = a_vault.sum_votes;
+    for voter in a_vault.voter_info.iter_mut() {
+        voter.pool_pct = if sum_votes.is_zero() {
+            Decimal::zero()
+        } else {
+            Decimal::from_ratio(voter.weight, sum_votes)
+        };
+    }
+}
+
+/// validate_bet returns an error if the sender may not add `weight` to their
+/// position in the vault, growing it to `position` in total
+fn validate_bet(
+    deps: Deps,
+    vault_id: u64,
+    a_vault: &Vault,
+    sender: &Addr,
+    weight: Uint128,
+    position: Uint128,
+) -> Result<(), ContractError> {
+    if a_vault.status != VaultStatus::DepositsOpen {
+        return Err(ContractError::VaultNotOpenForDeposits {});
+    }
+    if !is_vault_member(deps, vault_id, a_vault, sender)? {
+        return Err(ContractError::VaultNotMember {});
+    }
+    if weight < a_vault.min_bet {
+        return Err(ContractError::BetBelowMinimum { min_bet: a_vault.min_bet });
+    }
+    if let Some(max_bet) = a_vault.max_bet {
+        if position > max_bet {
+            return Err(ContractError::BetAboveMaximum { max_bet });
+        }
+    }
+    if let Some(max_pool) = a_vault.max_pool {
+        if a_vault.sum_votes + a_vault.committed_votes + weight > max_pool {
+            return Err(ContractError::VaultPoolCapReached { max_pool });
+        }
+    }
+    Ok(())
+}
+
+/// lock_bet locks `weight` of the sender's balance for the vault. Bets are
+/// locked against the deposited balance, so the new weight must fit next to
+/// everything already locked in other vaults (and this one).
+fn lock_bet(
+    storage: &mut dyn Storage,
+    sender: &Addr,
+    vault_id: u64,
+    weight: Uint128,
+) -> Result<(), ContractError> {
+    let key = sender.as_str().as_bytes();
+    let mut token_manager = bank_read(storage).may_load(key)?.unwrap_or_default();
+
+    let locked: Uint128 = token_manager.bet_token.iter().map(|(_, v)| *v).sum();
+    if locked + weight > token_manager.token_balance {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+    match token_manager.bet_token.iter_mut().find(|(k, _)| k == &vault_id) {
+        Some((_, locked_weight)) => *locked_weight += weight,
+        None => token_manager.bet_token.push((vault_id, weight)),
+    }
+    if !token_manager.participated_vaults.contains(&vault_id) {
+        token_manager.participated_vaults.push(vault_id);
+    }
+    bank(storage).save(key, &token_manager)?;
+    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn cast_bet(
-    deps: DepsMut,
+    mut deps: DepsMut,
     _env: Env,
     info: MessageInfo,
     vault_id: u64,
     vote: String,
     weight: Uint128,
+    referrer: Option<String>,
 ) -> Result<Response, ContractError> {
+    set_referrer(deps.branch(), &info.sender, referrer)?;
     let vault_key = &vault_id.to_be_bytes();
     let state = config_read(deps.storage).load()?;
-    if vault_id == 0 || state.vault_count > vault_id {
+    if vault_id == 0 || vault_id > state.vault_count {
         return Err(ContractError::VaultDoesNotExist {});
     }
     let mut a_vault = vault(deps.storage).load(vault_key)?;
-
-    if a_vault.status != VaultStatus::DepositsOpen {
-        return Err(ContractError::VaultNotOpenForDeposits {});
+    if !a_vault.kind.is_pooled() {
+        return Err(ContractError::VaultKindMismatch {});
     }
-
-    let key = info.sender.as_str().as_bytes();
-    let mut token_manager = bank_read(deps.storage).may_load(key)?.unwrap_or_default();
-
-    if token_manager.token_balance < weight {
-        return Err(ContractError::VaultInsufficientAmt {});
+    if a_vault.commit_reveal.is_some() {
+        return Err(ContractError::VaultRequiresCommitment {});
     }
-    token_manager.participated_vaults.push(vault_id);
-    token_manager.bet_token.push((vault_id, weight));
-    bank(deps.storage).save(key, &token_manager)?;
+    outcome_index(&a_vault, &vote)?;
 
-    a_vault.voters.push(info.sender.clone());
+    let idx = a_vault.voters.iter().position(|v| v == &info.sender);
+    let position = idx.map(|i| a_vault.voter_info[i].weight).unwrap_or_default() + weight;
+    validate_bet(deps.as_ref(), vault_id, &a_vault, &info.sender, weight, position)?;
+    ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
 
-    // Need to include pool_pct for the voter.
-    let pool_pct = 0.000;
-    let voter_info = Voter {vote, weight, pool_pct};
-    a_vault.voter_info.push(voter_info);
+    // One position per address: a repeated bet tops up the existing one,
+    // a bet on the other side moves it only if the vault allows switching.
+    match idx {
+        Some(idx) => {
+            let voter = &mut a_vault.voter_info[idx];
+            if voter.vote != vote {
+                if !a_vault.allow_side_switch {
+                    return Err(ContractError::VaultSenderVoted {});
+                }
+                voter.vote = vote.clone();
+            }
+            voter.weight = position;
+        }
+        None => {
+            a_vault.voters.push(info.sender.clone());
+            // pool_pct is filled in by tally_votes below.
+            let pool_pct = Decimal::zero();
+            let voter_info = Voter {vote: vote.clone(), weight, pool_pct};
+            a_vault.voter_info.push(voter_info);
+        }
+    }
+    tally_votes(&mut a_vault);
+    lock_bet(deps.storage, &info.sender, vault_id, weight)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_wagered += weight)?;
     vault(deps.storage).save(vault_key, &a_vault)?;
+    let bet = vault_event("bet", vault_id)
+        .add_attribute("outcome", &vote)
+        .add_attribute("amount", weight)
+        .add_attribute("position", position);
 
     Ok(Response::new()
         .add_attribute("action", "bet_submitted")
-        .add_attribute("vault_id", "&vault_id")
+        .add_attribute("vault_id", vault_id.to_string())
         .add_attribute("weight", weight)
-        .add_attribute("voter", &info.sender))
+        .add_attribute("voter", &info.sender)
+        .add_event(with_balance(bet, &info.sender, free_balance(deps.storage, &info.sender)?)))
 
 }
 
-
-fn send_tokens(to_address: &Addr, amount: Vec<Coin>, action: &str) -> Response {
-    
-    let r = Response::new()
-        .add_attribute("action", action)
-        .add_attribute("to", to_address.clone())
-        .add_message(CosmosMsg::Bank(BankMsg::Send { to_address: to_address.to_string(), amount: amount}));
-
-    return r;
-
+/// bet_commitment returns the commitment hiding a vote in a commit-reveal vault.
+/// The sender is part of the preimage so a commitment cannot be copied.
+pub fn bet_commitment(sender: &str, vote: &str, salt: &str) -> Binary {
+    let preimage = format!("{}:{}:{}", sender, vote, salt);
+    Binary::from(Sha256::digest(preimage.as_bytes()).as_slice())
 }
-/*
-pub fn beginFarm(
+
+/// commit_bet locks a hidden bet in a commit-reveal vault. The stake counts
+/// towards the vault's limits right away, the vote only once revealed.
+pub fn commit_bet(
     deps: DepsMut,
-    _env: Env,
     info: MessageInfo,
     vault_id: u64,
+    commitment: Binary,
+    weight: Uint128,
 ) -> Result<Response, ContractError> {
-    const anchorEarn = new AnchorEarn({
-        chain: CHAINS.TERRA,
-        network: NETWORKS.BOMBAY_12,
-        mnemonic: "result same slow despair clock arrow sunny glare flip caught vast wave space neither funny shiver disease lunch mercy unveil poet spring brass else",
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.commit_reveal.is_none() {
+        return Err(ContractError::VaultNotCommitReveal {});
+    }
+    let sender_key = info.sender.as_bytes();
+    if commitments_read(deps.storage, vault_id).may_load(sender_key)?.is_some() {
+        return Err(ContractError::VaultSenderVoted {});
+    }
+    validate_bet(deps.as_ref(), vault_id, &a_vault, &info.sender, weight, weight)?;
+
+    lock_bet(deps.storage, &info.sender, vault_id, weight)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_wagered += weight)?;
+    commitments(deps.storage, vault_id).save(sender_key, &Commitment { commitment, weight })?;
+    a_vault.committed_votes += weight;
+    vault(deps.storage).save(vault_key, &a_vault)?;
+    let committed = vault_event("commit_bet", vault_id).add_attribute("amount", weight);
+
+    Ok(Response::new()
+        .add_attribute("action", "commit_bet")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("weight", weight)
+        .add_attribute("voter", &info.sender)
+        .add_event(with_balance(committed, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// reveal_bet turns a commitment into a regular position once deposits have
+/// ended, provided the vote and salt hash to the committed value.
+pub fn reveal_bet(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    vote: String,
+    salt: String,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.status != VaultStatus::InProgress {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let reveal_deadline = match a_vault.reveal_deadline {
+        Some(reveal_deadline) => reveal_deadline,
+        None => return Err(ContractError::VaultNotCommitReveal {}),
+    };
+    if env.block.height > reveal_deadline {
+        return Err(ContractError::VaultRevealPeriodExpired { reveal_deadline });
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let commitment = match commitments_read(deps.storage, vault_id).may_load(sender_key)? {
+        Some(commitment) => commitment,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    if bet_commitment(info.sender.as_str(), &vote, &salt) != commitment.commitment {
+        return Err(ContractError::CommitmentMismatch {});
+    }
+    outcome_index(&a_vault, &vote)?;
+    commitments(deps.storage, vault_id).remove(sender_key);
+
+    a_vault.committed_votes = a_vault.committed_votes.checked_sub(commitment.weight)?;
+    // one position per address, as in cast_bet
+    match a_vault.voters.iter().position(|v| v == &info.sender) {
+        Some(idx) if a_vault.voter_info[idx].vote != vote => return Err(ContractError::VaultSenderVoted {}),
+        Some(idx) => a_vault.voter_info[idx].weight += commitment.weight,
+        None => {
+            a_vault.voters.push(info.sender.clone());
+            a_vault.voter_info.push(Voter {
+                vote: vote.clone(),
+                weight: commitment.weight,
+                pool_pct: Decimal::zero(),
+            });
+        }
+    }
+    tally_votes(&mut a_vault);
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "reveal_bet")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("vote", &vote)
+        .add_attribute("weight", commitment.weight)
+        .add_attribute("voter", &info.sender)
+        .add_event(
+            vault_event("reveal_bet", vault_id)
+                .add_attribute("outcome", vote)
+                .add_attribute("amount", commitment.weight)
+                .add_attribute("address", &info.sender),
+        ))
+}
+
+/// unlock_commitment releases a commitment's stake from the bettor's lock on
+/// the vault, leaving anything else locked there. Returns whether some of
+/// the lock remains.
+fn unlock_commitment(token_manager: &mut TokenManager, vault_id: u64, weight: Uint128) -> StdResult<bool> {
+    if let Some((_, locked_weight)) = token_manager.bet_token.iter_mut().find(|(k, _)| k == &vault_id) {
+        *locked_weight = locked_weight.checked_sub(weight)?;
+    }
+    token_manager.bet_token.retain(|(k, locked_weight)| k != &vault_id || !locked_weight.is_zero());
+    Ok(token_manager.bet_token.iter().any(|(k, _)| k == &vault_id))
+}
+
+/// expire_commitments settles every commitment left unrevealed after the
+/// reveal deadline. Depending on the vault, the stake is unlocked again or
+/// taken from the bettor's balance and kept in the pool. Anyone may call it.
+pub fn expire_commitments(
+    deps: DepsMut,
+    env: Env,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    let (reveal_deadline, refund) = match (a_vault.reveal_deadline, &a_vault.commit_reveal) {
+        (Some(reveal_deadline), Some(config)) => (reveal_deadline, config.refund_unrevealed),
+        _ => return Err(ContractError::VaultNotCommitReveal {}),
+    };
+    if env.block.height <= reveal_deadline {
+        return Err(ContractError::VaultRevealPeriodNotExpired { reveal_deadline });
+    }
+
+    let expired = commitments_read(deps.storage, vault_id)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut expired_weight = Uint128::zero();
+    for (voter_key, commitment) in &expired {
+        let mut token_manager = bank_read(deps.storage).load(voter_key)?;
+        let still_locked = unlock_commitment(&mut token_manager, vault_id, commitment.weight)?;
+        if refund && !still_locked {
+            token_manager.participated_vaults.retain(|k| k != &vault_id);
+        } else if !refund {
+            token_manager.token_balance =
+                token_manager.token_balance.checked_sub(commitment.weight)?;
+        }
+        bank(deps.storage).save(voter_key, &token_manager)?;
+        commitments(deps.storage, vault_id).remove(voter_key);
+        expired_weight += commitment.weight;
+    }
+
+    a_vault.committed_votes = a_vault.committed_votes.checked_sub(expired_weight)?;
+    if !refund {
+        a_vault.forfeited += expired_weight;
+    }
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "expire_commitments")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("count", expired.len().to_string())
+        .add_attribute(if refund { "refunded" } else { "forfeited" }, expired_weight)
+        .add_event(
+            vault_event("expire_commitments", vault_id)
+                .add_attribute("count", expired.len().to_string())
+                .add_attribute("amount", expired_weight)
+                .add_attribute("refunded", refund.to_string()),
+        ))
+}
+
+/// retract_bet lowers (or removes) the sender's position while deposits are
+/// open. The vault's early exit fee is taken from the retracted amount and
+/// stays in the pool; the rest is unlocked in the sender's balance.
+pub fn retract_bet(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.status != VaultStatus::DepositsOpen {
+        return Err(ContractError::VaultNotOpenForDeposits {});
+    }
+    if env.block.height >= a_vault.end_height {
+        return Err(ContractError::VaultDepositPeriodExpired {
+            expire_height: a_vault.end_height,
+        });
+    }
+
+    let idx = match a_vault.voters.iter().position(|v| v == &info.sender) {
+        Some(idx) => idx,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
+    let position = a_vault.voter_info[idx].weight;
+    if amount.is_zero() || amount > position {
+        return Err(ContractError::ExcessiveRetract { max_amount: position });
+    }
+    let remaining = position.checked_sub(amount)?;
+    if remaining.is_zero() {
+        a_vault.voters.remove(idx);
+        a_vault.voter_info.remove(idx);
+    } else {
+        a_vault.voter_info[idx].weight = remaining;
+    }
+
+    let fee = amount * a_vault.early_exit_fee;
+    if a_vault.cash_out.as_ref().is_some_and(|c| c.exit_fees_fund_reserve) {
+        a_vault.cash_out_reserve += fee;
+    } else {
+        a_vault.exit_fees += fee;
+    }
+    tally_votes(&mut a_vault);
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    let key = info.sender.as_str().as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(fee)?;
+    if remaining.is_zero() {
+        token_manager.bet_token.retain(|(k, _)| k != &vault_id);
+        token_manager.participated_vaults.retain(|k| k != &vault_id);
+    } else if let Some((_, locked_weight)) =
+        token_manager.bet_token.iter_mut().find(|(k, _)| k == &vault_id)
+    {
+        *locked_weight = remaining;
+    }
+    bank(deps.storage).save(key, &token_manager)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_won += amount - fee)?;
+    let retracted = vault_event("retract_bet", vault_id)
+        .add_attribute("amount", amount)
+        .add_attribute("fee", fee)
+        .add_attribute("position", remaining);
+
+    Ok(Response::new()
+        .add_attribute("action", "retract_bet")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("fee", fee)
+        .add_attribute("voter", &info.sender)
+        .add_event(with_balance(retracted, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// parimutuel_pot returns everything winners of a parimutuel vault split
+fn parimutuel_pot(a_vault: &Vault) -> Uint128 {
+    a_vault.sum_votes + a_vault.exit_fees + a_vault.forfeited
+}
+
+/// outcome_weight returns the total stake behind an outcome, including
+/// positions the vault bought back
+fn outcome_weight(a_vault: &Vault, outcome: &str) -> Uint128 {
+    let voters: Uint128 = a_vault
+        .voter_info
+        .iter()
+        .filter(|voter| voter.vote == outcome)
+        .map(|voter| voter.weight)
+        .sum();
+    let reserve: Uint128 = a_vault
+        .reserve_positions
+        .iter()
+        .filter(|(vote, _)| vote == outcome)
+        .map(|(_, weight)| *weight)
+        .sum();
+    voters + reserve
+}
+
+/// cash_out_offer returns a position's implied value, its share of the pot
+/// at the current odds, and what the vault pays for it after the discount
+fn cash_out_offer(a_vault: &Vault, weight: Uint128) -> (Uint128, Uint128) {
+    let value = if a_vault.sum_votes.is_zero() {
+        Uint128::zero()
+    } else {
+        weight.multiply_ratio(parimutuel_pot(a_vault), a_vault.sum_votes)
+    };
+    let discount = a_vault.cash_out.as_ref().map(|c| c.discount).unwrap_or_else(Decimal::zero);
+    (value, value.checked_sub(value * discount).unwrap_or_default())
+}
+
+/// fund_cash_out_reserve lets the creator top up the funds a vault uses to
+/// buy positions back
+pub fn fund_cash_out_reserve(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.creator != info.sender {
+        return Err(ContractError::VaultNotCreator {
+            creator: a_vault.creator.to_string(),
+            sender: info.sender.to_string(),
+        });
+    }
+    if a_vault.cash_out.is_none() {
+        return Err(ContractError::VaultCashOutDisabled {});
+    }
+    if matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    if free_balance(deps.storage, &info.sender)? < amount {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(amount)?;
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    a_vault.cash_out_reserve += amount;
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    let funded = vault_event("fund_cash_out_reserve", vault_id)
+        .add_attribute("amount", amount)
+        .add_attribute("reserve", a_vault.cash_out_reserve);
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_cash_out_reserve")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_event(with_balance(funded, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// cash_out sells the sender's whole position in a parimutuel vault to the
+/// vault's reserve before resolution. The stake stays in the pot, now held by
+/// the reserve, and the sender's lock is released in the same step.
+pub fn cash_out(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.cash_out.is_none() {
+        return Err(ContractError::VaultCashOutDisabled {});
+    }
+    if matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let idx = match a_vault.voters.iter().position(|v| v == &info.sender) {
+        Some(idx) => idx,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
+    let position = a_vault.voter_info[idx].clone();
+    let (value, offer) = cash_out_offer(&a_vault, position.weight);
+    if offer > a_vault.cash_out_reserve {
+        return Err(ContractError::CashOutReserveInsufficient {
+            available: a_vault.cash_out_reserve,
+        });
+    }
+
+    a_vault.voters.remove(idx);
+    a_vault.voter_info.remove(idx);
+    match a_vault.reserve_positions.iter_mut().find(|(vote, _)| vote == &position.vote) {
+        Some((_, weight)) => *weight += position.weight,
+        None => a_vault.reserve_positions.push((position.vote.clone(), position.weight)),
+    }
+    a_vault.cash_out_reserve = a_vault.cash_out_reserve.checked_sub(offer)?;
+    tally_votes(&mut a_vault);
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(position.weight)? + offer;
+    token_manager.bet_token.retain(|(k, _)| k != &vault_id);
+    token_manager.participated_vaults.retain(|k| k != &vault_id);
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    record_settlement(deps.storage, &info.sender, offer, offer > position.weight)?;
+    let cashed_out = vault_event("cash_out", vault_id)
+        .add_attribute("outcome", &position.vote)
+        .add_attribute("position", position.weight)
+        .add_attribute("value", value)
+        .add_attribute("payout", offer);
+
+    Ok(Response::new()
+        .add_attribute("action", "cash_out")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("position", position.weight)
+        .add_attribute("value", value)
+        .add_attribute("payout", offer)
+        .add_attribute("voter", &info.sender)
+        .add_event(with_balance(cashed_out, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// transfer_position moves `amount` of the sender's parimutuel position, and
+/// the stake locked behind it, to another address. The recipient's position
+/// grows if it is on the same side; the vault's totals are unchanged.
+pub fn transfer_position(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    recipient: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let recipient = deps.api.addr_validate(&recipient)?;
+    if recipient == info.sender {
+        return Err(ContractError::InvalidTransferRecipient {});
+    }
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !a_vault.kind.is_pooled() {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let idx = match a_vault.voters.iter().position(|v| v == &info.sender) {
+        Some(idx) => idx,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
+    ensure_no_receipt(deps.storage, vault_id, &recipient)?;
+    // a private vault's positions stay with its members, as in cast_bet
+    if !is_vault_member(deps.as_ref(), vault_id, &a_vault, &recipient)? {
+        return Err(ContractError::VaultNotMember {});
+    }
+    // the recipient's lock on the vault must stay the stake of one position
+    if commitments_read(deps.storage, vault_id).may_load(recipient.as_bytes())?.is_some() {
+        return Err(ContractError::RecipientHasCommitment {});
+    }
+    let Voter { vote, weight: position, .. } = a_vault.voter_info[idx].clone();
+    if amount.is_zero() || amount > position {
+        return Err(ContractError::ExcessiveTransfer { max_amount: position });
+    }
+
+    let to_idx = a_vault.voters.iter().position(|v| v == &recipient);
+    let received = to_idx.map(|i| a_vault.voter_info[i].weight).unwrap_or_default() + amount;
+    if a_vault.max_bet.is_some_and(|max_bet| received > max_bet) {
+        return Err(ContractError::BetAboveMaximum { max_bet: a_vault.max_bet.unwrap() });
+    }
+    match to_idx {
+        Some(to_idx) if a_vault.voter_info[to_idx].vote != vote => {
+            return Err(ContractError::RecipientVotedOtherSide {});
+        }
+        Some(to_idx) => a_vault.voter_info[to_idx].weight = received,
+        None => {
+            a_vault.voters.push(recipient.clone());
+            a_vault.voter_info.push(Voter { vote, weight: amount, pool_pct: Decimal::zero() });
+        }
+    }
+    let remaining = position.checked_sub(amount)?;
+    if remaining.is_zero() {
+        a_vault.voters.remove(idx);
+        a_vault.voter_info.remove(idx);
+    } else {
+        a_vault.voter_info[idx].weight = remaining;
+    }
+    tally_votes(&mut a_vault);
+    vault(deps.storage).save(vault_key, &a_vault)?;
+
+    // the stake moves with the position, so the recipient's lock is covered
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(amount)?;
+    if remaining.is_zero() {
+        token_manager.bet_token.retain(|(k, _)| k != &vault_id);
+        token_manager.participated_vaults.retain(|k| k != &vault_id);
+    } else if let Some((_, locked_weight)) =
+        token_manager.bet_token.iter_mut().find(|(k, _)| k == &vault_id)
+    {
+        *locked_weight = remaining;
+    }
+    bank(deps.storage).save(sender_key, &token_manager)?;
+
+    let recipient_key = recipient.as_bytes();
+    let mut token_manager = bank_read(deps.storage).may_load(recipient_key)?.unwrap_or_default();
+    token_manager.token_balance += amount;
+    bank(deps.storage).save(recipient_key, &token_manager)?;
+    lock_bet(deps.storage, &recipient, vault_id, amount)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_position")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("from", &info.sender)
+        .add_attribute("to", &recipient)
+        .add_event(with_balance(
+            vault_event("transfer_position", vault_id)
+                .add_attribute("amount", amount)
+                .add_attribute("from", &info.sender)
+                .add_attribute("to", &recipient),
+            &info.sender,
+            free_balance(deps.storage, &info.sender)?,
+        )))
+}
+
+/// is_vault_member returns whether the address may bet in the vault. Public
+/// vaults admit everyone; private ones check the allowlist, then the cw4 group.
+fn is_vault_member(deps: Deps, vault_id: u64, a_vault: &Vault, address: &Addr) -> StdResult<bool> {
+    if !a_vault.private {
+        return Ok(true);
+    }
+    if vault_members_read(deps.storage, vault_id).may_load(address.as_bytes())?.is_some() {
+        return Ok(true);
+    }
+    match &a_vault.member_group {
+        Some(group) => Ok(Cw4Contract(group.clone()).is_member(&deps.querier, address)?.is_some()),
+        None => Ok(false),
+    }
+}
+
+/// update_members lets the creator of a private vault add or remove allowlisted
+/// addresses while deposits are open. Removing a member keeps any bet already placed.
+pub fn update_members(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    addresses: Vec<String>,
+    add: bool,
+) -> Result<Response, ContractError> {
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.creator != info.sender {
+        return Err(ContractError::VaultNotCreator {
+            creator: a_vault.creator.to_string(),
+            sender: info.sender.to_string(),
+        });
+    }
+    if !a_vault.private {
+        return Err(ContractError::VaultNotPrivate {});
+    }
+    if a_vault.status != VaultStatus::DepositsOpen {
+        return Err(ContractError::VaultNotOpenForDeposits {});
+    }
+
+    for address in &addresses {
+        let member = deps.api.addr_validate(address)?;
+        if add {
+            vault_members(deps.storage, vault_id).save(member.as_bytes(), &true)?;
+        } else {
+            vault_members(deps.storage, vault_id).remove(member.as_bytes());
+        }
+    }
+
+    let action = if add { "add_members" } else { "remove_members" };
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("count", addresses.len().to_string())
+        .add_event(vault_event(action, vault_id).add_attribute("addresses", addresses.join(","))))
+}
+
+/// load_trading_vault returns a vault whose market is open to the sender:
+/// trading runs while deposits are open and ends at the deposit deadline
+fn load_trading_vault(deps: Deps, env: &Env, sender: &Addr, vault_id: u64) -> Result<Vault, ContractError> {
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.status != VaultStatus::DepositsOpen {
+        return Err(ContractError::VaultNotOpenForDeposits {});
+    }
+    if env.block.height >= a_vault.end_height {
+        return Err(ContractError::VaultDepositPeriodExpired {
+            expire_height: a_vault.end_height,
+        });
+    }
+    if !is_vault_member(deps, vault_id, &a_vault, sender)? {
+        return Err(ContractError::VaultNotMember {});
+    }
+    Ok(a_vault)
+}
+
+/// trade_shares buys or sells outcome shares of an Lmsr vault against the
+/// market maker. Buying spends the sender's free balance, selling credits it;
+/// `limit` caps the cost of a buy or floors the proceeds of a sell.
+#[allow(clippy::too_many_arguments)]
+pub fn trade_shares(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    outcome: String,
+    amount: Uint128,
+    limit: Option<Uint128>,
+    sell: bool,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = load_trading_vault(deps.as_ref(), &env, &info.sender, vault_id)?;
+    let liquidity = match a_vault.kind {
+        VaultKind::Lmsr { liquidity } => liquidity,
+        _ => return Err(ContractError::VaultKindMismatch {}),
+    };
+    let index = outcome_index(&a_vault, &outcome)?;
+    if amount.is_zero() {
+        return Err(ContractError::InvalidBetEvent {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut market = lmsr_market_read(deps.storage).load(vault_key)?;
+    let mut holdings = shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_else(|| vec![Uint128::zero(); a_vault.outcomes.len()]);
+    if sell && holdings[index] < amount {
+        return Err(ContractError::InsufficientShares {});
+    }
+
+    let price = lmsr::trade_amount(liquidity, &market.shares, index, amount, sell)?;
+    let mut token_manager = bank_read(deps.storage).may_load(sender_key)?.unwrap_or_default();
+    if sell {
+        if limit.is_some_and(|min_proceeds| price < min_proceeds) {
+            return Err(ContractError::SlippageExceeded { amount: price });
+        }
+        holdings[index] = holdings[index].checked_sub(amount)?;
+        market.shares[index] = market.shares[index].checked_sub(amount)?;
+        market.collateral = market.collateral.checked_sub(price)?;
+        token_manager.token_balance += price;
+    } else {
+        if limit.is_some_and(|max_cost| price > max_cost) {
+            return Err(ContractError::SlippageExceeded { amount: price });
+        }
+        if free_balance(deps.storage, &info.sender)? < price {
+            return Err(ContractError::VaultInsufficientAmt {});
+        }
+        holdings[index] += amount;
+        market.shares[index] += amount;
+        market.collateral += price;
+        token_manager.token_balance = token_manager.token_balance.checked_sub(price)?;
+        if !token_manager.participated_vaults.contains(&vault_id) {
+            token_manager.participated_vaults.push(vault_id);
+        }
+    }
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+    lmsr_market(deps.storage).save(vault_key, &market)?;
+    record_bettor(deps.storage, &info.sender, |stats| {
+        if sell {
+            stats.total_won += price
+        } else {
+            stats.total_wagered += price
+        }
+    })?;
+    let action = if sell { "sell_shares" } else { "buy_shares" };
+    let traded = vault_event(action, vault_id)
+        .add_attribute("outcome", &outcome)
+        .add_attribute("shares", amount)
+        .add_attribute("amount", price)
+        .add_attribute("holding", holdings[index]);
+
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("outcome", outcome)
+        .add_attribute("shares", amount)
+        .add_attribute("amount", price)
+        .add_attribute("trader", &info.sender)
+        .add_event(with_balance(traded, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// split_collateral turns `amount` of the sender's free balance into `amount`
+/// units of each outcome of an Amm vault
+pub fn split_collateral(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = load_trading_vault(deps.as_ref(), &env, &info.sender, vault_id)?;
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidBetEvent {});
+    }
+    if free_balance(deps.storage, &info.sender)? < amount {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(amount)?;
+    if !token_manager.participated_vaults.contains(&vault_id) {
+        token_manager.participated_vaults.push(vault_id);
+    }
+    bank(deps.storage).save(sender_key, &token_manager)?;
+
+    let mut holdings = shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_else(|| vec![Uint128::zero(); 2]);
+    for units in holdings.iter_mut() {
+        *units += amount;
+    }
+    shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+
+    let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+    pool.collateral += amount;
+    amm_pool(deps.storage).save(vault_key, &pool)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_wagered += amount)?;
+
+    let split = vault_event("split_collateral", vault_id).add_attribute("amount", amount);
+
+    Ok(Response::new()
+        .add_attribute("action", "split_collateral")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("trader", &info.sender)
+        .add_event(with_balance(split, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// merge_outcomes turns `amount` units of each outcome of an Amm vault back
+/// into collateral, which is how a trader exits before the vault resolves
+pub fn merge_outcomes(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if a_vault.status == VaultStatus::Finished {
+        return Err(ContractError::VaultNotOpenForDeposits {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut holdings = shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_else(|| vec![Uint128::zero(); 2]);
+    if holdings.iter().any(|units| *units < amount) {
+        return Err(ContractError::InsufficientShares {});
+    }
+    for units in holdings.iter_mut() {
+        *units = units.checked_sub(amount)?;
+    }
+    shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+
+    let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+    pool.collateral = pool.collateral.checked_sub(amount)?;
+    amm_pool(deps.storage).save(vault_key, &pool)?;
+
+    let mut token_manager = bank_read(deps.storage).may_load(sender_key)?.unwrap_or_default();
+    token_manager.token_balance += amount;
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_won += amount)?;
+
+    let merged = vault_event("merge_outcomes", vault_id).add_attribute("amount", amount);
+
+    Ok(Response::new()
+        .add_attribute("action", "merge_outcomes")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("trader", &info.sender)
+        .add_event(with_balance(merged, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// swap_outcome trades `amount` units of one outcome of an Amm vault for
+/// units of the other against the constant-product pool
+pub fn swap_outcome(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    offer_outcome: String,
+    amount: Uint128,
+    min_return: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = load_trading_vault(deps.as_ref(), &env, &info.sender, vault_id)?;
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    let offer = outcome_index(&a_vault, &offer_outcome)?;
+    let ask = 1 - offer;
+
+    let sender_key = info.sender.as_bytes();
+    let mut holdings = shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_else(|| vec![Uint128::zero(); 2]);
+    if amount.is_zero() || holdings[offer] < amount {
+        return Err(ContractError::InsufficientShares {});
+    }
+
+    let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+    if pool.reserves.iter().any(|r| r.is_zero()) {
+        return Err(ContractError::InvalidLiquidity {});
+    }
+    // the fee stays in the reserves, growing every liquidity provider's share
+    let fee = amount * pool.swap_fee;
+    let return_amount = amm::swap_return(&pool.reserves, offer, amount.checked_sub(fee)?);
+    if min_return.is_some_and(|min_return| return_amount < min_return) {
+        return Err(ContractError::SlippageExceeded { amount: return_amount });
+    }
+    pool.reserves[offer] += amount;
+    pool.reserves[ask] = pool.reserves[ask].checked_sub(return_amount)?;
+    amm_pool(deps.storage).save(vault_key, &pool)?;
+
+    holdings[offer] = holdings[offer].checked_sub(amount)?;
+    holdings[ask] += return_amount;
+    shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "swap_outcome")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("offer_outcome", &offer_outcome)
+        .add_attribute("amount", amount)
+        .add_attribute("return_amount", return_amount)
+        .add_attribute("fee", fee)
+        .add_attribute("trader", &info.sender)
+        .add_event(
+            vault_event("swap_outcome", vault_id)
+                .add_attribute("offer_outcome", offer_outcome)
+                .add_attribute("ask_outcome", &a_vault.outcomes[ask])
+                .add_attribute("amount", amount)
+                .add_attribute("return_amount", return_amount)
+                .add_attribute("fee", fee)
+                .add_attribute("address", &info.sender),
+        ))
+}
+
+/// provide_liquidity adds `amount` of the sender's free balance to an Amm
+/// pool at its current prices, minting pool shares in proportion
+pub fn provide_liquidity(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = load_trading_vault(deps.as_ref(), &env, &info.sender, vault_id)?;
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if amount.is_zero() {
+        return Err(ContractError::InvalidLiquidity {});
+    }
+    if free_balance(deps.storage, &info.sender)? < amount {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+
+    let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+    let max_reserve = pool.reserves.iter().max().copied().unwrap_or_default();
+    let minted = if pool.total_lp_shares.is_zero() {
+        amount
+    } else if max_reserve.is_zero() {
+        return Err(ContractError::InvalidLiquidity {});
+    } else {
+        amount.multiply_ratio(pool.total_lp_shares, max_reserve)
+    };
+    if minted.is_zero() {
+        return Err(ContractError::InvalidLiquidity {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(amount)?;
+    if !token_manager.participated_vaults.contains(&vault_id) {
+        token_manager.participated_vaults.push(vault_id);
+    }
+    bank(deps.storage).save(sender_key, &token_manager)?;
+
+    // units the pool cannot take without moving its prices go to the provider
+    let added = amm::provision(&pool.reserves, amount);
+    let mut holdings = shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_else(|| vec![Uint128::zero(); 2]);
+    for (i, units) in added.iter().enumerate() {
+        pool.reserves[i] += *units;
+        holdings[i] += amount.checked_sub(*units)?;
+    }
+    if holdings.iter().any(|units| !units.is_zero()) {
+        shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+    }
+    pool.collateral += amount;
+    pool.total_lp_shares += minted;
+    amm_pool(deps.storage).save(vault_key, &pool)?;
+
+    let position = lp_shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_default();
+    lp_shares(deps.storage, vault_id).save(sender_key, &(position + minted))?;
+    let provided = vault_event("provide_liquidity", vault_id)
+        .add_attribute("amount", amount)
+        .add_attribute("lp_shares", minted)
+        .add_attribute("total_lp_shares", pool.total_lp_shares);
+
+    Ok(Response::new()
+        .add_attribute("action", "provide_liquidity")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("lp_shares", minted)
+        .add_attribute("provider", &info.sender)
+        .add_event(with_balance(provided, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// withdraw_liquidity burns pool shares for the matching part of an Amm
+/// pool's reserves. Before resolution they are paid out as outcome units;
+/// afterwards the winning units (or, in a voided vault, all of them) are
+/// redeemed for collateral straight away.
+pub fn withdraw_liquidity(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    ensure_final(&a_vault)?;
+
+    let sender_key = info.sender.as_bytes();
+    let position = lp_shares_read(deps.storage, vault_id)
+        .may_load(sender_key)?
+        .unwrap_or_default();
+    if amount.is_zero() || position < amount {
+        return Err(ContractError::InsufficientLpShares {});
+    }
+    if position == amount {
+        lp_shares(deps.storage, vault_id).remove(sender_key);
+    } else {
+        lp_shares(deps.storage, vault_id).save(sender_key, &position.checked_sub(amount)?)?;
+    }
+
+    let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+    let withdrawn: Vec<Uint128> = pool
+        .reserves
+        .iter()
+        .map(|r| r.multiply_ratio(amount, pool.total_lp_shares))
+        .collect();
+    for (reserve, units) in pool.reserves.iter_mut().zip(&withdrawn) {
+        *reserve = reserve.checked_sub(*units)?;
+    }
+    pool.total_lp_shares = pool.total_lp_shares.checked_sub(amount)?;
+
+    let mut payout = Uint128::zero();
+    if matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        payout = if a_vault.status == VaultStatus::Closed {
+            void_payout(&a_vault, &withdrawn)
+        } else {
+            withdrawn[outcome_index(&a_vault, &a_vault.result)?]
+        };
+        pool.collateral = pool.collateral.checked_sub(payout)?;
+        let mut token_manager = bank_read(deps.storage).may_load(sender_key)?.unwrap_or_default();
+        token_manager.token_balance += payout;
+        bank(deps.storage).save(sender_key, &token_manager)?;
+    } else {
+        let mut holdings = shares_read(deps.storage, vault_id)
+            .may_load(sender_key)?
+            .unwrap_or_else(|| vec![Uint128::zero(); 2]);
+        for (held, units) in holdings.iter_mut().zip(&withdrawn) {
+            *held += *units;
+        }
+        shares(deps.storage, vault_id).save(sender_key, &holdings)?;
+    }
+    amm_pool(deps.storage).save(vault_key, &pool)?;
+    let removed = vault_event("withdraw_liquidity", vault_id)
+        .add_attribute("lp_shares", amount)
+        .add_attribute("payout", payout)
+        .add_attribute("total_lp_shares", pool.total_lp_shares);
+
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_liquidity")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("lp_shares", amount)
+        .add_attribute("payout", payout)
+        .add_attribute("provider", &info.sender)
+        .add_event(with_balance(removed, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// resolve_vault lets the creator settle a vault on one of its outcomes once
+/// deposits have ended (and every commitment has been revealed or expired).
+/// The Lmsr market maker keeps what the winning shares redeem for and returns
+/// the rest of its collateral to the creator once the result is final, which
+/// is right away unless the vault has a dispute period, see dispute_result.
+pub fn resolve_vault(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    outcome: String,
+) -> Result<Response, ContractError> {
+    finish_vault(deps, env, info, vault_id, Some(outcome), None)
+}
+
+/// resolve_with_value settles a vault on a numeric value. A Scalar vault
+/// splits its pot by it; a bucketed vault resolves on the bucket holding it.
+pub fn resolve_with_value(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    value: Decimal,
+) -> Result<Response, ContractError> {
+    finish_vault(deps, env, info, vault_id, None, Some(value))
+}
+
+/// result_outcome checks what a vault is resolved with, an outcome or, for a
+/// Scalar or bucketed vault, a value, and returns the outcome it stands for
+fn result_outcome(
+    a_vault: &Vault,
+    outcome: Option<String>,
+    resolved_value: Option<Decimal>,
+) -> Result<String, ContractError> {
+    let by_value = matches!(a_vault.kind, VaultKind::Scalar { .. }) || a_vault.buckets.is_some();
+    Ok(match (outcome, resolved_value) {
+        (Some(outcome), None) if !by_value => {
+            outcome_index(a_vault, &outcome)?;
+            outcome
+        }
+        (None, Some(value)) if by_value => match &a_vault.buckets {
+            Some(buckets) => a_vault.outcomes[bucket_index(buckets, value)].clone(),
+            None => value.to_string(),
+        },
+        _ => return Err(ContractError::VaultKindMismatch {}),
+    })
+}
+
+/// settle_result makes a resolved vault's result final. Positions can be
+/// claimed on it from then on, vaults conditional on another outcome are
+/// cancelled, and the creator gets the bond still held back along with what
+/// the market maker or the cash out reserve has left, which is returned.
+fn settle_result(
+    storage: &mut dyn Storage,
+    vault_id: u64,
+    a_vault: &mut Vault,
+    height: u64,
+) -> Result<Uint128, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let residual = match a_vault.kind {
+        VaultKind::Lmsr { .. } => {
+            let index = outcome_index(a_vault, &a_vault.result)?;
+            let mut market = lmsr_market_read(storage).load(vault_key)?;
+            let residual = market.collateral.checked_sub(market.shares[index])?;
+            market.collateral = market.shares[index];
+            lmsr_market(storage).save(vault_key, &market)?;
+            residual
+        }
+        // the pool's own winning units are redeemed by its liquidity providers
+        VaultKind::Amm { .. } => Uint128::zero(),
+        // whatever the reserve did not spend, plus what its positions won
+        VaultKind::Parimutuel | VaultKind::Scalar { .. } => {
+            let won: Uint128 = a_vault
+                .reserve_positions
+                .iter()
+                .map(|(vote, weight)| position_payout(a_vault, vote, *weight))
+                .sum();
+            a_vault.cash_out_reserve + won
+        }
+    };
+    let residual = residual + a_vault.bond;
+    a_vault.bond = Uint128::zero();
+    a_vault.final_height = Some(height);
+    vault(storage).save(vault_key, a_vault)?;
+    cancel_conditional_vaults(storage, a_vault, Some(&a_vault.result))?;
+    if !residual.is_zero() {
+        let creator_key = a_vault.creator.as_bytes();
+        let mut token_manager = bank_read(storage).may_load(creator_key)?.unwrap_or_default();
+        token_manager.token_balance += residual;
+        bank(storage).save(creator_key, &token_manager)?;
+    }
+    Ok(residual)
+}
+
+fn finish_vault(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    outcome: Option<String>,
+    resolved_value: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.creator != info.sender {
+        return Err(ContractError::VaultNotCreator {
+            creator: a_vault.creator.to_string(),
+            sender: info.sender.to_string(),
+        });
+    }
+    if a_vault.status != VaultStatus::InProgress {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let outcome = result_outcome(&a_vault, outcome, resolved_value)?;
+    if let Some(reveal_deadline) = a_vault.reveal_deadline {
+        if env.block.height <= reveal_deadline {
+            return Err(ContractError::VaultRevealPeriodNotExpired { reveal_deadline });
+        }
+        if !a_vault.committed_votes.is_zero() {
+            return Err(ContractError::VaultHasUnrevealedCommitments {});
+        }
+    }
+    // a parent settling any other way has cancelled this vault already
+    if let Some(condition) = &a_vault.condition {
+        let parent = vault_read(deps.storage).load(&condition.vault_id.to_be_bytes())?;
+        if parent.final_height.is_none() {
+            return Err(ContractError::VaultConditionPending {});
+        }
+    }
+
+    let previous = a_vault.status.clone();
+    a_vault.status = VaultStatus::Finished;
+    a_vault.result = outcome.clone();
+    a_vault.resolved_value = resolved_value;
+    a_vault.resolved_height = Some(env.block.height);
+    // a vault resolved in time keeps its creation bond until the result is
+    // final, a late one forfeits it to the treasury as if it had expired
+    let on_time = env.block.height <= resolve_deadline(deps.storage, &a_vault)?;
+    let slashed = if on_time { Uint128::zero() } else { a_vault.bond };
+    a_vault.bond -= slashed;
+    // exit fees and forfeits of a pool nobody is left in have no one to go to
+    let unclaimed = if a_vault.kind.is_pooled() && a_vault.sum_votes.is_zero() {
+        a_vault.exit_fees + a_vault.forfeited
+    } else {
+        Uint128::zero()
+    };
+    vault(deps.storage).save(vault_key, &a_vault)?;
+    if !(slashed + unclaimed).is_zero() {
+        let treasury = config_read(deps.storage).load()?.treasury;
+        let mut token_manager = bank_read(deps.storage).may_load(treasury.as_bytes())?.unwrap_or_default();
+        token_manager.token_balance += slashed + unclaimed;
+        bank(deps.storage).save(treasury.as_bytes(), &token_manager)?;
+    }
+    record_creator(deps.storage, &a_vault.creator, |stats| {
+        if on_time {
+            stats.resolved_on_time += 1
+        } else {
+            stats.resolved_late += 1
+        }
+    })?;
+    // without a dispute period there is nothing to wait for
+    let (bond, residual) = if a_vault.dispute_period == 0 {
+        let bond = a_vault.bond;
+        (bond, settle_result(deps.storage, vault_id, &mut a_vault, env.block.height)?)
+    } else {
+        (Uint128::zero(), Uint128::zero())
+    };
+    let mut resolved = vault_event("resolve", vault_id).add_attribute("outcome", &outcome);
+    if let Some(value) = resolved_value {
+        resolved = resolved.add_attribute("resolved_value", value.to_string());
+    }
+    let resolved = with_status(resolved, &previous, &a_vault.status)
+        .add_attribute("on_time", on_time.to_string())
+        .add_attribute("bond_returned", bond)
+        .add_attribute("slashed", slashed)
+        .add_attribute("creator_payout", residual);
+    let resolved = with_balance(resolved, &info.sender, free_balance(deps.storage, &info.sender)?);
+
+    Ok(Response::new()
+        .add_attribute("action", "resolve_vault")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("outcome", outcome)
+        .add_event(resolved))
+}
+
+/// dispute_deadline returns the last block a resolved vault's result may be
+/// disputed in
+fn dispute_deadline(a_vault: &Vault) -> u64 {
+    a_vault.resolved_height.unwrap_or_default() + a_vault.dispute_period
+}
+
+/// ensure_final refuses to pay out on a result that may still be disputed
+fn ensure_final(a_vault: &Vault) -> Result<(), ContractError> {
+    if a_vault.status == VaultStatus::Finished && a_vault.final_height.is_none() {
+        return Err(ContractError::VaultResultNotFinal {});
+    }
+    Ok(())
+}
+
+/// dispute_result lets anyone holding a position or pool shares in a vault
+/// challenge its result within the dispute period after it was resolved.
+/// Nothing is paid out on a disputed result until the owner rules on it.
+pub fn dispute_result(deps: DepsMut, env: Env, info: MessageInfo, vault_id: u64) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.status != VaultStatus::Finished || a_vault.final_height.is_some() {
+        return Err(ContractError::VaultNotInDisputePeriod {});
+    }
+    if a_vault.dispute.is_some() {
+        return Err(ContractError::VaultDisputed {});
+    }
+    let dispute_deadline = dispute_deadline(&a_vault);
+    if env.block.height > dispute_deadline {
+        return Err(ContractError::VaultDisputePeriodExpired { dispute_deadline });
+    }
+    let sender_key = info.sender.as_bytes();
+    let staked = if a_vault.kind.is_pooled() {
+        a_vault.voters.contains(&info.sender)
+    } else {
+        shares_read(deps.storage, vault_id).may_load(sender_key)?.is_some()
+            || lp_shares_read(deps.storage, vault_id).may_load(sender_key)?.is_some()
+    };
+    if !staked {
+        return Err(ContractError::VaultNoBet {});
+    }
+
+    a_vault.dispute = Some(DisputeStatus::Open);
+    vault(deps.storage).save(vault_key, &a_vault)?;
+    record_creator(deps.storage, &a_vault.creator, |stats| stats.disputed += 1)?;
+    let disputed = vault_event("dispute_result", vault_id)
+        .add_attribute("outcome", &a_vault.result)
+        .add_attribute("disputer", &info.sender);
+
+    Ok(Response::new()
+        .add_attribute("action", "dispute_result")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("disputer", &info.sender)
+        .add_event(disputed))
+}
+
+/// settle_dispute lets the owner rule on a disputed result by naming the
+/// outcome (or value) the vault should have resolved on. The same one upholds
+/// the result; any other overturns it and slashes the creation bond to the
+/// treasury. Either way the result is final afterwards.
+pub fn settle_dispute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+    outcome: Option<String>,
+    value: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    let state = config_read(deps.storage).load()?;
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.dispute != Some(DisputeStatus::Open) {
+        return Err(ContractError::VaultNotDisputed {});
+    }
+    let outcome = result_outcome(&a_vault, outcome, value)?;
+
+    let overturned = outcome != a_vault.result || value != a_vault.resolved_value;
+    let mut slashed = Uint128::zero();
+    if overturned {
+        a_vault.result = outcome;
+        a_vault.resolved_value = value;
+        a_vault.dispute = Some(DisputeStatus::Overturned);
+        slashed = a_vault.bond;
+        a_vault.bond = Uint128::zero();
+        let treasury_key = state.treasury.as_bytes();
+        let mut token_manager = bank_read(deps.storage).may_load(treasury_key)?.unwrap_or_default();
+        token_manager.token_balance += slashed;
+        bank(deps.storage).save(treasury_key, &token_manager)?;
+        record_creator(deps.storage, &a_vault.creator, |stats| stats.overturned += 1)?;
+    } else {
+        a_vault.dispute = Some(DisputeStatus::Upheld);
+    }
+    let bond = a_vault.bond;
+    let residual = settle_result(deps.storage, vault_id, &mut a_vault, env.block.height)?;
+    let mut settled = vault_event("settle_dispute", vault_id).add_attribute("outcome", &a_vault.result);
+    if let Some(value) = a_vault.resolved_value {
+        settled = settled.add_attribute("resolved_value", value.to_string());
+    }
+    let settled = settled
+        .add_attribute("overturned", overturned.to_string())
+        .add_attribute("bond_returned", bond)
+        .add_attribute("slashed", slashed)
+        .add_attribute("creator_payout", residual);
+
+    Ok(Response::new()
+        .add_attribute("action", "settle_dispute")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("outcome", &a_vault.result)
+        .add_event(settled))
+}
+
+/// finalize_vault lets anyone make a result final once its dispute period
+/// passed without a dispute. A dispute the owner has not ruled on within
+/// another dispute period lapses, and the result stands.
+pub fn finalize_vault(deps: DepsMut, env: Env, vault_id: u64) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if a_vault.status != VaultStatus::Finished || a_vault.final_height.is_some() {
+        return Err(ContractError::VaultNotInDisputePeriod {});
+    }
+    let mut dispute_deadline = dispute_deadline(&a_vault);
+    if a_vault.dispute.is_some() {
+        dispute_deadline += a_vault.dispute_period;
+    }
+    if env.block.height <= dispute_deadline {
+        return Err(ContractError::VaultDisputePeriodNotExpired { dispute_deadline });
+    }
+
+    if a_vault.dispute.is_some() {
+        a_vault.dispute = Some(DisputeStatus::Upheld);
+    }
+    let bond = a_vault.bond;
+    let residual = settle_result(deps.storage, vault_id, &mut a_vault, env.block.height)?;
+    let finalized = vault_event("finalize_vault", vault_id)
+        .add_attribute("outcome", &a_vault.result)
+        .add_attribute("bond_returned", bond)
+        .add_attribute("creator_payout", residual);
+
+    Ok(Response::new()
+        .add_attribute("action", "finalize_vault")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_event(finalized))
+}
+
+/// update_config lets the owner change the creation bond, the resolution
+/// period, the treasury and the protocol fee with its referral share.
+/// Existing vaults keep the bond terms they were created on.
+#[allow(clippy::too_many_arguments)]
+pub fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    creation_bond: Option<Uint128>,
+    resolution_period: Option<u64>,
+    dispute_period: Option<u64>,
+    treasury: Option<String>,
+    protocol_fee: Option<Decimal>,
+    referral_share: Option<Decimal>,
+) -> Result<Response, ContractError> {
+    let mut state = config_read(deps.storage).load()?;
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if protocol_fee.into_iter().chain(referral_share).any(|fee| fee > Decimal::one()) {
+        return Err(ContractError::InvalidProtocolFee {});
+    }
+    if let Some(protocol_fee) = protocol_fee {
+        state.protocol_fee = protocol_fee;
+    }
+    if let Some(referral_share) = referral_share {
+        state.referral_share = referral_share;
+    }
+    if let Some(creation_bond) = creation_bond {
+        state.creation_bond = creation_bond;
+    }
+    if let Some(resolution_period) = resolution_period {
+        state.resolution_period = resolution_period;
+    }
+    if let Some(dispute_period) = dispute_period {
+        state.dispute_period = dispute_period;
+    }
+    if let Some(treasury) = treasury {
+        state.treasury = deps.api.addr_validate(&treasury)?;
+    }
+    config(deps.storage).save(&state)?;
+
+    Ok(Response::new().add_attribute("action", "update_config").add_event(
+        event("update_config")
+            .add_attribute("creation_bond", state.creation_bond)
+            .add_attribute("resolution_period", state.resolution_period.to_string())
+            .add_attribute("dispute_period", state.dispute_period.to_string())
+            .add_attribute("treasury", &state.treasury)
+            .add_attribute("protocol_fee", state.protocol_fee.to_string())
+            .add_attribute("referral_share", state.referral_share.to_string()),
+    ))
+}
+
+/// expire_vault lets anyone close a vault its creator left unresolved for the
+/// resolution period after deposits (or reveals) ended. The creation bond is
+/// slashed to the treasury. A pooled vault is cancelled so every stake can be
+/// claimed back; an Lmsr or Amm vault is voided so every outcome unit redeems
+/// for an equal share of a complete set, see void_payout.
+pub fn expire_vault(deps: DepsMut, env: Env, vault_id: u64) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let mut a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !matches!(a_vault.status, VaultStatus::DepositsOpen | VaultStatus::InProgress) {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let resolve_deadline = resolve_deadline(deps.storage, &a_vault)?;
+    if env.block.height <= resolve_deadline {
+        return Err(ContractError::VaultResolutionPeriodNotExpired { resolve_deadline });
+    }
+
+    let previous = a_vault.status.clone();
+    let slashed = a_vault.bond;
+    let treasury = config_read(deps.storage).load()?.treasury;
+    if !slashed.is_zero() {
+        let treasury_key = treasury.as_bytes();
+        let mut token_manager = bank_read(deps.storage).may_load(treasury_key)?.unwrap_or_default();
+        token_manager.token_balance += slashed;
+        bank(deps.storage).save(treasury_key, &token_manager)?;
+        a_vault.bond = Uint128::zero();
+    }
+    if a_vault.kind.is_pooled() {
+        cancel_vault(deps.storage, vault_id, &mut a_vault, true)?;
+    } else {
+        void_market(deps.storage, vault_id, &mut a_vault)?;
+    }
+    record_creator(deps.storage, &a_vault.creator, |stats| stats.expired += 1)?;
+
+    let expired = with_status(
+        vault_event("expire_vault", vault_id).add_attribute("slashed", slashed),
+        &previous,
+        &a_vault.status,
+    );
+    let expired = with_balance(expired, &treasury, free_balance(deps.storage, &treasury)?);
+
+    Ok(Response::new()
+        .add_attribute("action", "expire_vault")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("slashed", slashed)
+        .add_event(expired))
+}
+
+/// claim_winnings settles the sender's position in a finished vault. In a
+/// parimutuel vault winners split the whole pot (exit fees and forfeits
+/// included) pro rata and losers give up their stake; if nobody backed the
+/// result every stake shares the pot. Scalar vaults split the pot between long
+/// and short by the resolved value. Winning Lmsr shares redeem 1:1.
+pub fn claim_winnings(
+    deps: DepsMut,
+    info: MessageInfo,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    let vault_key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(vault_key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotOpenForWithdrawals {});
+    }
+    ensure_final(&a_vault)?;
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).may_load(sender_key)?.unwrap_or_default();
+    let payout = match a_vault.kind {
+        VaultKind::Parimutuel | VaultKind::Scalar { .. } => {
+            ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
+            settle_position(deps.storage, &a_vault, vault_id, &info.sender, &info.sender)?
+        }
+        VaultKind::Lmsr { .. } | VaultKind::Amm { .. } => {
+            let holdings = match shares_read(deps.storage, vault_id).may_load(sender_key)? {
+                Some(holdings) => holdings,
+                None => return Err(ContractError::VaultNoBet {}),
+            };
+            let payout = if a_vault.status == VaultStatus::Closed {
+                void_payout(&a_vault, &holdings)
+            } else {
+                holdings[outcome_index(&a_vault, &a_vault.result)?]
+            };
+            if let VaultKind::Lmsr { .. } = a_vault.kind {
+                let mut market = lmsr_market_read(deps.storage).load(vault_key)?;
+                market.collateral = market.collateral.checked_sub(payout)?;
+                lmsr_market(deps.storage).save(vault_key, &market)?;
+            } else {
+                let mut pool = amm_pool_read(deps.storage).load(vault_key)?;
+                pool.collateral = pool.collateral.checked_sub(payout)?;
+                amm_pool(deps.storage).save(vault_key, &pool)?;
+            }
+            shares(deps.storage, vault_id).remove(sender_key);
+            token_manager.token_balance += payout;
+            bank(deps.storage).save(sender_key, &token_manager)?;
+            let win = a_vault.status == VaultStatus::Finished && !payout.is_zero();
+            record_settlement(deps.storage, &info.sender, payout, win)?;
+            payout
+        }
+    };
+
+    let claimed = vault_event("claim", vault_id).add_attribute("payout", payout);
+
+    Ok(Response::new()
+        .add_attribute("action", "claim_winnings")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("payout", payout)
+        .add_attribute("voter", &info.sender)
+        .add_event(with_balance(claimed, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// position_payout returns what a stake of `weight` on `vote` receives from
+/// a finished pooled vault; a cancelled vault returns every stake. Parimutuel
+/// winners split the pot pro rata; if nobody backed the result every stake
+/// gets its share of the pot back, exit fees and forfeits included. Scalar vaults give the long side the pot's share that
+/// the resolved value reached between the bounds and the short side the
+/// rest; with one side empty the other takes the whole pot.
+fn position_payout(a_vault: &Vault, vote: &str, weight: Uint128) -> Uint128 {
+    if a_vault.status == VaultStatus::Closed {
+        return weight;
+    }
+    let pot = parimutuel_pot(a_vault);
+    if let (VaultKind::Scalar { lower, upper }, Some(value)) = (&a_vault.kind, a_vault.resolved_value) {
+        let long = outcome_weight(a_vault, "long");
+        let short = outcome_weight(a_vault, "short");
+        if long.is_zero() || short.is_zero() {
+            return weight.multiply_ratio(pot, long + short);
+        }
+        let long_pot = pot * scalar_fraction(*lower, *upper, value);
+        return if vote == "long" {
+            weight.multiply_ratio(long_pot, long)
+        } else {
+            weight.multiply_ratio(pot - long_pot, short)
+        };
+    }
+    let winning = outcome_weight(a_vault, &a_vault.result);
+    if winning.is_zero() {
+        weight.multiply_ratio(pot, a_vault.sum_votes)
+    } else if vote == a_vault.result {
+        weight.multiply_ratio(pot, winning)
+    } else {
+        Uint128::zero()
+    }
+}
+
+/// scalar_fraction returns where `value` falls between the bounds, from 0 at
+/// or below `lower` to 1 at or above `upper`
+fn scalar_fraction(lower: Decimal, upper: Decimal, value: Decimal) -> Decimal {
+    if value <= lower {
+        Decimal::zero()
+    } else if value >= upper {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio((value - lower).numerator(), (upper - lower).numerator())
+    }
+}
+
+/// take_protocol_fee takes the protocol fee from a winner's profit, crediting
+/// the winner's referrer with their share and the treasury with the rest.
+/// Returns the whole fee.
+fn take_protocol_fee(storage: &mut dyn Storage, winner: &Addr, profit: Uint128) -> StdResult<Uint128> {
+    let state = config_read(storage).load()?;
+    let fee = profit * state.protocol_fee;
+    if fee.is_zero() {
+        return Ok(fee);
+    }
+    let mut credits = vec![];
+    let mut rest = fee;
+    if let Some(referrer_addr) = referrer_read(storage).may_load(winner.as_bytes())? {
+        let share = fee * state.referral_share;
+        let earned = referral_earnings_read(storage).may_load(referrer_addr.as_bytes())?.unwrap_or_default();
+        referral_earnings(storage).save(referrer_addr.as_bytes(), &(earned + share))?;
+        credits.push((referrer_addr, share));
+        rest = fee - share;
+    }
+    credits.push((state.treasury, rest));
+    for (address, amount) in credits {
+        let mut token_manager = bank_read(storage).may_load(address.as_bytes())?.unwrap_or_default();
+        token_manager.token_balance += amount;
+        bank(storage).save(address.as_bytes(), &token_manager)?;
+    }
+    Ok(fee)
+}
+
+/// settle_position settles a bettor's position in a finished parimutuel
+/// vault, paying whatever it won to `recipient`
+fn settle_position(
+    storage: &mut dyn Storage,
+    a_vault: &Vault,
+    vault_id: u64,
+    bettor: &Addr,
+    recipient: &Addr,
+) -> Result<Uint128, ContractError> {
+    let bettor_key = bettor.as_bytes();
+    let mut token_manager = bank_read(storage).may_load(bettor_key)?.unwrap_or_default();
+    let weight = match token_manager.bet_token.iter().find(|(k, _)| k == &vault_id) {
+        Some((_, weight)) => *weight,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    let idx = match a_vault.voters.iter().position(|v| v == bettor) {
+        Some(idx) => idx,
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    let payout = position_payout(a_vault, &a_vault.voter_info[idx].vote, weight);
+    let payout = payout - take_protocol_fee(storage, recipient, payout.saturating_sub(weight))?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(weight)?;
+    bank(storage).save(bettor_key, &token_manager)?;
+    allow_claim(storage, bettor, vault_id)?;
+
+    let recipient_key = recipient.as_bytes();
+    let mut token_manager = bank_read(storage).may_load(recipient_key)?.unwrap_or_default();
+    token_manager.token_balance += payout;
+    bank(storage).save(recipient_key, &token_manager)?;
+    // whoever holds the position when it settles gets what it paid, while
+    // its stake stays with whoever placed it
+    record_settlement(storage, recipient, payout, payout > weight)?;
+    Ok(payout)
+}
+
+/// ensure_no_receipt returns an error if the bettor's position has been
+/// minted as a receipt, which makes it belong to whoever holds the token
+fn ensure_no_receipt(storage: &dyn Storage, vault_id: u64, bettor: &Addr) -> Result<(), ContractError> {
+    match receipts_read(storage, vault_id).may_load(bettor.as_bytes())? {
+        Some(token_id) => Err(ContractError::PositionHasReceipt { token_id }),
+        None => Ok(()),
+    }
+}
+
+/// mint_receipt mints the sender's position in a parimutuel vault as a cw721
+/// token. From then on the position is frozen and its payout goes to
+/// whoever owns the token when the vault is claimed.
+pub fn mint_receipt(deps: DepsMut, info: MessageInfo, vault_id: u64) -> Result<Response, ContractError> {
+    let position_nft = match config_read(deps.storage).load()?.position_nft {
+        Some(position_nft) => position_nft,
+        None => return Err(ContractError::PositionNftNotConfigured {}),
+    };
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(ContractError::VaultDoesNotExist {}),
+    };
+    if !a_vault.kind.is_pooled() {
+        return Err(ContractError::VaultKindMismatch {});
+    }
+    if matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotInProgress {});
+    }
+    let voter = match a_vault.voters.iter().position(|v| v == &info.sender) {
+        Some(idx) => &a_vault.voter_info[idx],
+        None => return Err(ContractError::VaultNoBet {}),
+    };
+    ensure_no_receipt(deps.storage, vault_id, &info.sender)?;
+
+    let token_id = format!("{}:{}", vault_id, info.sender);
+    receipts(deps.storage, vault_id).save(info.sender.as_bytes(), &token_id)?;
+    let mint = Cw721ExecuteMsg::Mint(MintMsg {
+        token_id: token_id.clone(),
+        owner: info.sender.to_string(),
+        name: format!("CowBet vault {} position", vault_id),
+        description: Some(format!("{} {} on \"{}\"", voter.weight, voter.vote, a_vault.description)),
+        image: None,
+    });
+    let mint = WasmMsg::Execute {
+        contract_addr: position_nft.to_string(),
+        msg: to_binary(&mint)?,
+        funds: vec![],
+    };
+
+    Ok(Response::new()
+        .add_message(mint)
+        .add_attribute("action", "mint_receipt")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("token_id", &token_id)
+        .add_attribute("voter", &info.sender)
+        .add_event(
+            vault_event("mint_receipt", vault_id)
+                .add_attribute("token_id", token_id)
+                .add_attribute("address", &info.sender),
+        ))
+}
+
+/// claim_receipt settles a receipted position for the current owner of its token
+pub fn claim_receipt(deps: DepsMut, info: MessageInfo, token_id: String) -> Result<Response, ContractError> {
+    let invalid = || ContractError::InvalidReceipt { token_id: token_id.clone() };
+    let (vault_id, bettor) = match token_id.split_once(':') {
+        Some((vault_id, bettor)) => (vault_id.parse::<u64>().map_err(|_| invalid())?, Addr::unchecked(bettor)),
+        None => return Err(invalid()),
+    };
+    if receipts_read(deps.storage, vault_id).may_load(bettor.as_bytes())?.as_ref() != Some(&token_id) {
+        return Err(invalid());
+    }
+    let a_vault = vault_read(deps.storage).load(&vault_id.to_be_bytes())?;
+    if !matches!(a_vault.status, VaultStatus::Finished | VaultStatus::Closed) {
+        return Err(ContractError::VaultNotOpenForWithdrawals {});
+    }
+    ensure_final(&a_vault)?;
+
+    let position_nft = match config_read(deps.storage).load()?.position_nft {
+        Some(position_nft) => position_nft,
+        None => return Err(ContractError::PositionNftNotConfigured {}),
+    };
+    let owner: OwnerOfResponse = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: position_nft.to_string(),
+        msg: to_binary(&Cw721QueryMsg::OwnerOf {
+            token_id: token_id.clone(),
+            include_expired: None,
+        })?,
+    }))?;
+    if owner.owner != info.sender.as_str() {
+        return Err(ContractError::NotReceiptOwner { token_id });
+    }
+
+    let payout = settle_position(deps.storage, &a_vault, vault_id, &bettor, &info.sender)?;
+    let claimed = vault_event("claim_receipt", vault_id)
+        .add_attribute("token_id", &token_id)
+        .add_attribute("payout", payout);
+
+    Ok(Response::new()
+        .add_attribute("action", "claim_receipt")
+        .add_attribute("vault_id", vault_id.to_string())
+        .add_attribute("token_id", token_id)
+        .add_attribute("payout", payout)
+        .add_attribute("owner", &info.sender)
+        .add_event(with_balance(claimed, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// fund_parlay_reserve lets the owner add to the funds backing parlay payouts
+pub fn fund_parlay_reserve(deps: DepsMut, info: MessageInfo, amount: Uint128) -> Result<Response, ContractError> {
+    let mut state = config_read(deps.storage).load()?;
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if free_balance(deps.storage, &info.sender)? < amount {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(amount)?;
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    state.parlay_reserve += amount;
+    config(deps.storage).save(&state)?;
+
+    let funded = event("fund_parlay_reserve")
+        .add_attribute("amount", amount)
+        .add_attribute("reserve", state.parlay_reserve);
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_parlay_reserve")
+        .add_attribute("amount", amount)
+        .add_event(with_balance(funded, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// withdraw_parlay_reserve returns reserve funds not backing open parlays to
+/// the owner
+pub fn withdraw_parlay_reserve(deps: DepsMut, info: MessageInfo, amount: Uint128) -> Result<Response, ContractError> {
+    let mut state = config_read(deps.storage).load()?;
+    if state.owner != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    if amount > state.parlay_reserve {
+        return Err(ContractError::ParlayReserveInsufficient {
+            available: state.parlay_reserve,
+        });
+    }
+    state.parlay_reserve -= amount;
+    config(deps.storage).save(&state)?;
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).may_load(sender_key)?.unwrap_or_default();
+    token_manager.token_balance += amount;
+    bank(deps.storage).save(sender_key, &token_manager)?;
+
+    let withdrawn = event("withdraw_parlay_reserve")
+        .add_attribute("amount", amount)
+        .add_attribute("reserve", state.parlay_reserve);
+
+    Ok(Response::new()
+        .add_attribute("action", "withdraw_parlay_reserve")
+        .add_attribute("amount", amount)
+        .add_event(with_balance(withdrawn, &info.sender, free_balance(deps.storage, &info.sender)?)))
+}
+
+/// leg_probability returns the implied probability of an outcome: its share
+/// of the stakes in a parimutuel vault, or its price in an Amm pool. Odds
+/// that can be moved before placing a parlay and moved back for free after
+/// price no leg: a parimutuel vault needs an early exit fee and no side
+/// switching, an Amm pool a swap fee, and an Lmsr market sells back at the
+/// price it buys. Nor may a leg pay more than MAX_LEG_ODDS times its stake.
+fn leg_probability(deps: Deps, a_vault: &Vault, vault_id: u64, outcome: &str) -> Result<Decimal, ContractError> {
+    let index = outcome_index(a_vault, outcome)?;
+    let probability = match a_vault.kind {
+        VaultKind::Parimutuel if a_vault.early_exit_fee.is_zero() || a_vault.allow_side_switch => {
+            return Err(ContractError::ParlayLegUnwindable { vault_id });
+        }
+        VaultKind::Parimutuel if a_vault.sum_votes.is_zero() => Decimal::zero(),
+        VaultKind::Parimutuel => Decimal::from_ratio(outcome_weight(a_vault, outcome), a_vault.sum_votes),
+        VaultKind::Amm { .. } => {
+            let pool = amm_pool_read(deps.storage).load(&vault_id.to_be_bytes())?;
+            if pool.swap_fee.is_zero() {
+                return Err(ContractError::ParlayLegUnwindable { vault_id });
+            }
+            amm::prices(&pool.reserves)[index]
+        }
+        VaultKind::Lmsr { .. } => return Err(ContractError::ParlayLegUnwindable { vault_id }),
+        // a linear payout has no single outcome to back
+        VaultKind::Scalar { .. } => return Err(ContractError::VaultKindMismatch {}),
+    };
+    if probability.is_zero() {
+        return Err(ContractError::ParlayLegUnpriced { vault_id });
+    }
+    if probability < Decimal::from_ratio(1u64, MAX_LEG_ODDS) {
+        return Err(ContractError::ParlayLegOddsTooLong { vault_id, max_odds: MAX_LEG_ODDS });
+    }
+    Ok(probability)
+}
+
+/// parlay_payout returns what `stake` pays at the odds of the given legs,
+/// the product of one over each leg's implied probability. With the odds of
+/// every leg capped the product fits a Uint256; a payout that does not fit
+/// a Uint128 is an error.
+fn parlay_payout<'a>(stake: Uint128, legs: impl Iterator<Item = &'a ParlayLeg>) -> StdResult<Uint128> {
+    let payout = legs.fold(Uint256::from(stake), |payout, leg| {
+        payout.multiply_ratio(leg.probability.denominator(), leg.probability.numerator())
+    });
+    Ok(payout.try_into()?)
+}
+
+/// place_parlay stakes the sender's free balance on every leg winning. The
+/// odds are fixed at placement from each leg's implied probability, and the
+/// house reserve sets aside whatever the parlay may win beyond its stake.
+pub fn place_parlay(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    legs: Vec<ParlayLegMsg>,
+    stake: Uint128,
+    min_payout: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    let mut vault_ids: Vec<u64> = legs.iter().map(|leg| leg.vault_id).collect();
+    vault_ids.sort_unstable();
+    vault_ids.dedup();
+    if legs.len() < 2 || legs.len() as u64 > MAX_PARLAY_LEGS || vault_ids.len() != legs.len() {
+        return Err(ContractError::InvalidParlay { max_legs: MAX_PARLAY_LEGS });
+    }
+    if stake.is_zero() {
+        return Err(ContractError::InvalidBetEvent {});
+    }
+    if free_balance(deps.storage, &info.sender)? < stake {
+        return Err(ContractError::VaultInsufficientAmt {});
+    }
+
+    let mut priced = vec![];
+    for leg in legs {
+        let a_vault = load_trading_vault(deps.as_ref(), &env, &info.sender, leg.vault_id)?;
+        let probability = leg_probability(deps.as_ref(), &a_vault, leg.vault_id, &leg.outcome)?;
+        priced.push(ParlayLeg {
+            vault_id: leg.vault_id,
+            outcome: leg.outcome,
+            probability,
+        });
+    }
+    let payout = parlay_payout(stake, priced.iter())?;
+    if let Some(min_payout) = min_payout {
+        if payout < min_payout {
+            return Err(ContractError::SlippageExceeded { amount: payout });
+        }
+    }
+    let mut state = config_read(deps.storage).load()?;
+    let liability = payout - stake;
+    if liability > state.parlay_reserve {
+        return Err(ContractError::ParlayReserveInsufficient {
+            available: state.parlay_reserve,
+        });
+    }
+
+    let sender_key = info.sender.as_bytes();
+    let mut token_manager = bank_read(deps.storage).load(sender_key)?;
+    token_manager.token_balance = token_manager.token_balance.checked_sub(stake)?;
+    bank(deps.storage).save(sender_key, &token_manager)?;
+    record_bettor(deps.storage, &info.sender, |stats| stats.total_wagered += stake)?;
+
+    state.parlay_count += 1;
+    state.parlay_reserve -= liability;
+    let parlay_id = state.parlay_count;
+    config(deps.storage).save(&state)?;
+    let new_parlay = Parlay {
+        owner: info.sender.clone(),
+        legs: priced,
+        stake,
+        payout,
+        status: ParlayStatus::Open,
+    };
+    parlay(deps.storage).save(&parlay_id.to_be_bytes(), &new_parlay)?;
+    let legs: Vec<String> = new_parlay
+        .legs
+        .iter()
+        .map(|leg| format!("{}:{}", leg.vault_id, leg.outcome))
+        .collect();
+    let placed = event("place_parlay")
+        .add_attribute("parlay_id", parlay_id.to_string())
+        .add_attribute("legs", legs.join(","))
+        .add_attribute("stake", stake)
+        .add_attribute("payout", payout);
+
+    Ok(Response::new()
+        .add_attribute("action", "place_parlay")
+        .add_attribute("parlay_id", parlay_id.to_string())
+        .add_attribute("stake", stake)
+        .add_attribute("payout", payout)
+        .add_attribute("bettor", &info.sender)
+        .add_event(with_balance(placed, &info.sender, free_balance(deps.storage, &info.sender)?))
+        .set_data(to_binary(&PlaceParlayResponse { parlay_id })?))
+}
+
+/// leg_status returns how a parlay leg on `outcome` stands. A leg is void if
+/// its vault was cancelled or nobody backed the result, and Open until its
+/// vault's result is final.
+fn leg_status(a_vault: &Vault, outcome: &str) -> ParlayStatus {
+    match a_vault.status {
+        VaultStatus::Closed => ParlayStatus::Void,
+        VaultStatus::Finished if a_vault.final_height.is_none() => ParlayStatus::Open,
+        VaultStatus::Finished
+            if a_vault.kind.is_pooled() && outcome_weight(a_vault, &a_vault.result).is_zero() =>
+        {
+            ParlayStatus::Void
+        }
+        VaultStatus::Finished if a_vault.result == outcome => ParlayStatus::Won,
+        VaultStatus::Finished => ParlayStatus::Lost,
+        VaultStatus::DepositsOpen | VaultStatus::InProgress => ParlayStatus::Open,
+    }
+}
+
+/// settle_parlay pays out a parlay once one of its legs lost or all of them
+/// resolved. Void legs drop out of the odds and a parlay whose legs are all
+/// void returns its stake. Anyone may settle; the payout always goes to the
+/// parlay's owner and the rest of what the reserve set aside goes back to it.
+pub fn settle_parlay(deps: DepsMut, parlay_id: u64) -> Result<Response, ContractError> {
+    let parlay_key = &parlay_id.to_be_bytes();
+    let mut a_parlay = match parlay_read(deps.storage).may_load(parlay_key)? {
+        Some(a_parlay) => a_parlay,
+        None => return Err(ContractError::ParlayDoesNotExist {}),
+    };
+    if a_parlay.status != ParlayStatus::Open {
+        return Err(ContractError::ParlaySettled {});
+    }
+
+    let mut statuses = vec![];
+    for leg in a_parlay.legs.iter() {
+        let a_vault = vault_read(deps.storage).load(&leg.vault_id.to_be_bytes())?;
+        statuses.push(leg_status(&a_vault, &leg.outcome));
+    }
+    let (status, payout) = if statuses.contains(&ParlayStatus::Lost) {
+        (ParlayStatus::Lost, Uint128::zero())
+    } else if statuses.contains(&ParlayStatus::Open) {
+        return Err(ContractError::ParlayNotSettled {});
+    } else if statuses.contains(&ParlayStatus::Won) {
+        let won = a_parlay
+            .legs
+            .iter()
+            .zip(statuses.iter())
+            .filter(|(_, status)| **status == ParlayStatus::Won)
+            .map(|(leg, _)| leg);
+        (ParlayStatus::Won, parlay_payout(a_parlay.stake, won)?)
+    } else {
+        (ParlayStatus::Void, a_parlay.stake)
+    };
+
+    a_parlay.status = status;
+    parlay(deps.storage).save(parlay_key, &a_parlay)?;
+    let mut state = config_read(deps.storage).load()?;
+    state.parlay_reserve += a_parlay.payout - payout;
+    config(deps.storage).save(&state)?;
+    record_settlement(deps.storage, &a_parlay.owner, payout, payout > a_parlay.stake)?;
+    if !payout.is_zero() {
+        let owner_key = a_parlay.owner.as_bytes();
+        let mut token_manager = bank_read(deps.storage).may_load(owner_key)?.unwrap_or_default();
+        token_manager.token_balance += payout;
+        bank(deps.storage).save(owner_key, &token_manager)?;
+    }
+
+    let settled = event("settle_parlay")
+        .add_attribute("parlay_id", parlay_id.to_string())
+        .add_attribute("status", parlay_status_name(&a_parlay.status))
+        .add_attribute("payout", payout);
+    let balance = free_balance(deps.storage, &a_parlay.owner)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "settle_parlay")
+        .add_attribute("parlay_id", parlay_id.to_string())
+        .add_attribute("payout", payout)
+        .add_attribute("bettor", &a_parlay.owner)
+        .add_event(with_balance(settled, &a_parlay.owner, balance)))
+}
+
+fn send_tokens(to_address: &Addr, amount: Vec<Coin>, action: &str) -> Response {
+    
+    let r = Response::new()
+        .add_attribute("action", action)
+        .add_attribute("to", to_address.clone())
+        .add_message(CosmosMsg::Bank(BankMsg::Send { to_address: to_address.to_string(), amount: amount}));
+
+    return r;
+
+}
+/*
+pub fn beginFarm(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    vault_id: u64,
+) -> Result<Response, ContractError> {
+    const anchorEarn = new AnchorEarn({
+        chain: CHAINS.TERRA,
+        network: NETWORKS.BOMBAY_12,
+        mnemonic: "result same slow despair clock arrow sunny glare flip caught vast wave space neither funny shiver disease lunch mercy unveil poet spring brass else",
     })
 }*/
 
 
-#[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Config {} => to_binary(&config_read(deps.storage).load()?),
-        QueryMsg::TokenBet { address } => {
-            token_balance(deps, deps.api.addr_validate(address.as_str())?)
+#[entry_point]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&config_read(deps.storage).load()?),
+        QueryMsg::TokenBet { address } => {
+            token_balance(deps, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::Positions { address } => {
+            query_positions(deps, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::Vault { vault_id } => query_vault(deps, vault_id),
+        QueryMsg::VaultMember { vault_id, address } => {
+            query_vault_member(deps, vault_id, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::Prices { vault_id } => query_prices(deps, vault_id),
+        QueryMsg::SimulateTrade {
+            vault_id,
+            outcome,
+            shares,
+            sell,
+        } => query_simulate_trade(deps, vault_id, outcome, shares, sell),
+        QueryMsg::Shares { vault_id, address } => {
+            query_shares(deps, vault_id, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::SimulateSwap {
+            vault_id,
+            offer_outcome,
+            amount,
+        } => query_simulate_swap(deps, vault_id, offer_outcome, amount),
+        QueryMsg::Pool { vault_id } => query_pool(deps, vault_id),
+        QueryMsg::Receipt { vault_id, address } => {
+            let address = deps.api.addr_validate(address.as_str())?;
+            let token_id = receipts_read(deps.storage, vault_id).may_load(address.as_bytes())?;
+            to_binary(&ReceiptResponse { token_id })
+        }
+        QueryMsg::CashOutQuote { vault_id, address } => {
+            query_cash_out_quote(deps, vault_id, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::LpShares { vault_id, address } => {
+            query_lp_shares(deps, vault_id, deps.api.addr_validate(address.as_str())?)
+        }
+        QueryMsg::Parlay { parlay_id } => query_parlay(deps, parlay_id),
+        QueryMsg::VaultsByCategory {
+            category,
+            start_after,
+            limit,
+        } => query_vault_list(category_index_read(deps.storage, &category), start_after, limit),
+        QueryMsg::VaultsByTag {
+            tag,
+            start_after,
+            limit,
+        } => query_vault_list(tag_index_read(deps.storage, &tag), start_after, limit),
+        QueryMsg::CreatorStats { address } => {
+            let address = deps.api.addr_validate(address.as_str())?;
+            let stats = creator_stats_read(deps.storage).may_load(address.as_bytes())?.unwrap_or_default();
+            to_binary(&stats)
+        }
+        QueryMsg::Referral { address } => {
+            let address = deps.api.addr_validate(address.as_str())?;
+            let resp = ReferralResponse {
+                referrer: referrer_read(deps.storage).may_load(address.as_bytes())?.map(|r| r.to_string()),
+                earnings: referral_earnings_read(deps.storage).may_load(address.as_bytes())?.unwrap_or_default(),
+            };
+            to_binary(&resp)
+        }
+        QueryMsg::BettorStats { address } => {
+            let address = deps.api.addr_validate(address.as_str())?;
+            let stats = bettor_stats_read(deps.storage).may_load(address.as_bytes())?.unwrap_or_default();
+            to_binary(&bettor_stats_response(&address, stats))
+        }
+        QueryMsg::Leaderboard {
+            metric,
+            start_after,
+            limit,
+        } => query_leaderboard(deps, metric, start_after, limit),
+        QueryMsg::Template { template_id } => {
+            match template_read(deps.storage).may_load(&template_id.to_be_bytes())? {
+                Some(a_template) => to_binary(&a_template),
+                None => Err(StdError::generic_err("Template does not exist")),
+            }
+        }
+        QueryMsg::Series { series_id } => {
+            match series_read(deps.storage).may_load(&series_id.to_be_bytes())? {
+                Some(a_series) => to_binary(&a_series),
+                None => Err(StdError::generic_err("Series does not exist")),
+            }
+        }
+    }
+}
+
+fn query_vault(deps: Deps, vault_id: u64) -> StdResult<Binary> {
+    let key = &vault_id.to_be_bytes();
+
+    let vault = match vault_read(deps.storage).may_load(key)? {
+        Some(vault) => Some(vault),
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    }
+    .unwrap();
+
+    let resp = VaultResponse {
+        creator: vault.creator.to_string(),
+        status: vault.status,
+        kind: vault.kind,
+        outcomes: vault.outcomes,
+        result: vault.result,
+        end_height: Some(vault.end_height),
+        start_height: vault.start_height,
+        description: vault.description,
+        allow_side_switch: vault.allow_side_switch,
+        early_exit_fee: vault.early_exit_fee,
+        exit_fees: vault.exit_fees,
+        min_bet: vault.min_bet,
+        max_bet: vault.max_bet,
+        max_pool: vault.max_pool,
+        private: vault.private,
+        member_group: vault.member_group.map(|group| group.to_string()),
+        commit_reveal: vault.commit_reveal,
+        committed_votes: vault.committed_votes,
+        reveal_deadline: vault.reveal_deadline,
+        forfeited: vault.forfeited,
+        cash_out: vault.cash_out,
+        cash_out_reserve: vault.cash_out_reserve,
+        reserve_positions: vault.reserve_positions,
+        resolved_value: vault.resolved_value,
+        buckets: vault.buckets,
+        condition: vault.condition,
+        conditional_vaults: vault.conditional_vaults,
+        series_id: vault.series_id,
+        metadata: vault.metadata,
+        bond: vault.bond,
+        resolution_period: vault.resolution_period,
+        resolved_height: vault.resolved_height,
+        dispute_period: vault.dispute_period,
+        dispute: vault.dispute,
+        final_height: vault.final_height,
+    };
+    to_binary(&resp)
+}
+
+fn bettor_stats_response(address: &Addr, stats: BettorStats) -> BettorStatsResponse {
+    let net_pnl = if stats.total_won >= stats.total_wagered {
+        (stats.total_won - stats.total_wagered).to_string()
+    } else {
+        format!("-{}", stats.total_wagered - stats.total_won)
+    };
+    BettorStatsResponse {
+        address: address.to_string(),
+        total_wagered: stats.total_wagered,
+        total_won: stats.total_won,
+        net_pnl,
+        wins: stats.wins,
+        vaults_participated: stats.vaults_participated,
+    }
+}
+
+/// query_leaderboard pages through the addresses ranked by a metric, best
+/// first. Ties are ordered by address, descending.
+fn query_leaderboard(
+    deps: Deps,
+    metric: LeaderboardMetric,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = match start_after {
+        Some(address) => {
+            let address = deps.api.addr_validate(&address)?;
+            let stats = bettor_stats_read(deps.storage).may_load(address.as_bytes())?.unwrap_or_default();
+            Some(leaderboard_key(&stats, metric, &address))
+        }
+        None => None,
+    };
+    let entries = leaderboard_read(deps.storage, metric_name(metric))
+        .range(None, end.as_deref(), Order::Descending)
+        .take(limit)
+        .map(|item| {
+            let (key, _) = item?;
+            let address = Addr::unchecked(String::from_utf8(key[16..].to_vec())?);
+            let stats = bettor_stats_read(deps.storage).load(address.as_bytes())?;
+            Ok(bettor_stats_response(&address, stats))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    to_binary(&LeaderboardResponse { entries })
+}
+
+/// query_vault_list pages through the vault ids of a secondary index
+fn query_vault_list(index: ReadonlyBucket<bool>, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    // id followed by a zero byte sorts right after id, even for u64::MAX
+    let start = start_after.map(|id| {
+        let mut key = id.to_be_bytes().to_vec();
+        key.push(0);
+        key
+    });
+    let vault_ids = index
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, _) = item?;
+            let key: [u8; 8] = key
+                .try_into()
+                .map_err(|_| StdError::generic_err("Corrupted vault index"))?;
+            Ok(u64::from_be_bytes(key))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    to_binary(&VaultListResponse { vault_ids })
+}
+
+fn query_parlay(deps: Deps, parlay_id: u64) -> StdResult<Binary> {
+    let a_parlay = match parlay_read(deps.storage).may_load(&parlay_id.to_be_bytes())? {
+        Some(a_parlay) => a_parlay,
+        None => return Err(StdError::generic_err("Parlay does not exist")),
+    };
+    let resp = ParlayResponse {
+        owner: a_parlay.owner.to_string(),
+        legs: a_parlay.legs,
+        stake: a_parlay.stake,
+        payout: a_parlay.payout,
+        status: a_parlay.status,
+    };
+    to_binary(&resp)
+}
+
+fn query_cash_out_quote(deps: Deps, vault_id: u64, address: Addr) -> StdResult<Binary> {
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let position = match a_vault.voters.iter().position(|v| v == &address) {
+        Some(idx) => a_vault.voter_info[idx].weight,
+        None => Uint128::zero(),
+    };
+    let (value, offer) = cash_out_offer(&a_vault, position);
+    let resp = CashOutQuoteResponse {
+        position,
+        value,
+        offer,
+        cash_out_reserve: a_vault.cash_out_reserve,
+    };
+    to_binary(&resp)
+}
+
+fn query_vault_member(deps: Deps, vault_id: u64, address: Addr) -> StdResult<Binary> {
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let resp = VaultMemberResponse {
+        member: is_vault_member(deps, vault_id, &a_vault, &address)?,
+    };
+    to_binary(&resp)
+}
+
+/// load_lmsr returns an Lmsr vault with its market and liquidity parameter
+fn load_lmsr(deps: Deps, vault_id: u64) -> StdResult<(Vault, LmsrMarket, Uint128)> {
+    let key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let liquidity = match a_vault.kind {
+        VaultKind::Lmsr { liquidity } => liquidity,
+        _ => return Err(StdError::generic_err("Vault is not an Lmsr market")),
+    };
+    let market = lmsr_market_read(deps.storage).load(key)?;
+    Ok((a_vault, market, liquidity))
+}
+
+fn outcome_prices(a_vault: &Vault, prices: Vec<Decimal>) -> Vec<OutcomePrice> {
+    a_vault
+        .outcomes
+        .iter()
+        .zip(prices)
+        .map(|(outcome, price)| OutcomePrice { outcome: outcome.clone(), price })
+        .collect()
+}
+
+fn query_prices(deps: Deps, vault_id: u64) -> StdResult<Binary> {
+    let key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let prices = match a_vault.kind {
+        VaultKind::Lmsr { liquidity } => {
+            lmsr::prices(liquidity, &lmsr_market_read(deps.storage).load(key)?.shares)
+        }
+        VaultKind::Amm { .. } => amm::prices(&amm_pool_read(deps.storage).load(key)?.reserves),
+        VaultKind::Parimutuel | VaultKind::Scalar { .. } => {
+            return Err(StdError::generic_err("Vault has no market prices"))
+        }
+    };
+    let resp = PricesResponse {
+        prices: outcome_prices(&a_vault, prices),
+    };
+    to_binary(&resp)
+}
+
+fn query_simulate_trade(
+    deps: Deps,
+    vault_id: u64,
+    outcome: String,
+    amount: Uint128,
+    sell: bool,
+) -> StdResult<Binary> {
+    let (a_vault, market, liquidity) = load_lmsr(deps, vault_id)?;
+    let index = match a_vault.outcomes.iter().position(|o| o == &outcome) {
+        Some(index) => index,
+        None => return Err(StdError::generic_err("Invalid bet event")),
+    };
+    let price = lmsr::trade_amount(liquidity, &market.shares, index, amount, sell)?;
+    let mut after = market.shares.clone();
+    if sell {
+        after[index] = after[index].checked_sub(amount)?;
+    } else {
+        after[index] = after[index].checked_add(amount)?;
+    }
+    let resp = SimulateTradeResponse {
+        amount: price,
+        prices: outcome_prices(&a_vault, lmsr::prices(liquidity, &after)),
+    };
+    to_binary(&resp)
+}
+
+fn query_simulate_swap(
+    deps: Deps,
+    vault_id: u64,
+    offer_outcome: String,
+    amount: Uint128,
+) -> StdResult<Binary> {
+    let key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    if !matches!(a_vault.kind, VaultKind::Amm { .. }) {
+        return Err(StdError::generic_err("Vault is not an Amm market"));
+    }
+    let offer = match a_vault.outcomes.iter().position(|o| o == &offer_outcome) {
+        Some(offer) => offer,
+        None => return Err(StdError::generic_err("Invalid bet event")),
+    };
+    let pool = amm_pool_read(deps.storage).load(key)?;
+    let fee = amount * pool.swap_fee;
+    let return_amount = amm::swap_return(&pool.reserves, offer, amount.checked_sub(fee)?);
+    let mut after = pool.reserves.clone();
+    after[offer] += amount;
+    after[1 - offer] = after[1 - offer].checked_sub(return_amount)?;
+    let resp = SimulateSwapResponse {
+        return_amount,
+        prices: outcome_prices(&a_vault, amm::prices(&after)),
+    };
+    to_binary(&resp)
+}
+
+/// query_positions lists what an address holds in the vaults it entered and
+/// has not claimed from yet
+fn query_positions(deps: Deps, address: Addr) -> StdResult<Binary> {
+    let token_manager = bank_read(deps.storage).may_load(address.as_bytes())?.unwrap_or_default();
+    let mut positions = vec![];
+    for vault_id in token_manager.participated_vaults.iter().copied() {
+        let a_vault = vault_read(deps.storage).load(&vault_id.to_be_bytes())?;
+        let (outcome, stake, shares) = if a_vault.kind.is_pooled() {
+            let stake = match token_manager.bet_token.iter().find(|(k, _)| k == &vault_id) {
+                Some((_, weight)) => *weight,
+                None => continue,
+            };
+            let idx = a_vault.voters.iter().position(|v| v == &address);
+            (idx.map(|idx| a_vault.voter_info[idx].vote.clone()), stake, vec![])
+        } else {
+            let holdings = match shares_read(deps.storage, vault_id).may_load(address.as_bytes())? {
+                Some(holdings) => holdings,
+                None => continue,
+            };
+            let shares = a_vault
+                .outcomes
+                .iter()
+                .zip(holdings)
+                .map(|(outcome, shares)| OutcomeShares { outcome: outcome.clone(), shares })
+                .collect();
+            (None, Uint128::zero(), shares)
+        };
+        positions.push(PositionResponse {
+            vault_id,
+            status: a_vault.status,
+            outcome,
+            stake,
+            shares,
+        });
+    }
+    to_binary(&PositionsResponse { positions })
+}
+
+fn query_shares(deps: Deps, vault_id: u64, address: Addr) -> StdResult<Binary> {
+    let a_vault = match vault_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let holdings = shares_read(deps.storage, vault_id)
+        .may_load(address.as_bytes())?
+        .unwrap_or_else(|| vec![Uint128::zero(); a_vault.outcomes.len()]);
+    let resp = SharesResponse {
+        shares: a_vault
+            .outcomes
+            .into_iter()
+            .zip(holdings)
+            .map(|(outcome, shares)| OutcomeShares { outcome, shares })
+            .collect(),
+    };
+    to_binary(&resp)
+}
+
+fn query_pool(deps: Deps, vault_id: u64) -> StdResult<Binary> {
+    let key = &vault_id.to_be_bytes();
+    let a_vault = match vault_read(deps.storage).may_load(key)? {
+        Some(a_vault) => a_vault,
+        None => return Err(StdError::generic_err("Vault does not exist")),
+    };
+    let pool = match amm_pool_read(deps.storage).may_load(key)? {
+        Some(pool) => pool,
+        None => return Err(StdError::generic_err("Vault is not an Amm market")),
+    };
+    let resp = PoolResponse {
+        reserves: a_vault
+            .outcomes
+            .into_iter()
+            .zip(pool.reserves)
+            .map(|(outcome, shares)| OutcomeShares { outcome, shares })
+            .collect(),
+        collateral: pool.collateral,
+        swap_fee: pool.swap_fee,
+        total_lp_shares: pool.total_lp_shares,
+    };
+    to_binary(&resp)
+}
+
+fn query_lp_shares(deps: Deps, vault_id: u64, address: Addr) -> StdResult<Binary> {
+    let pool = match amm_pool_read(deps.storage).may_load(&vault_id.to_be_bytes())? {
+        Some(pool) => pool,
+        None => return Err(StdError::generic_err("Vault is not an Amm market")),
+    };
+    let resp = LpSharesResponse {
+        shares: lp_shares_read(deps.storage, vault_id)
+            .may_load(address.as_bytes())?
+            .unwrap_or_default(),
+        total_lp_shares: pool.total_lp_shares,
+    };
+    to_binary(&resp)
+}
+
+fn token_balance(deps: Deps, address: Addr) -> StdResult<Binary> {
+    let token_manager = bank_read(deps.storage)
+        .may_load(address.as_str().as_bytes())?
+        .unwrap_or_default();
+
+    let resp = TokenBetResponse {
+        token_balance: token_manager.token_balance,
+    };
+
+    to_binary(&resp)
+}
+
+/*pub fn try_increment(deps: DepsMut) -> Result<Response, ContractError> {
+    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
+        state.count += 1;
+        Ok(state)
+    })?;
+
+    Ok(Response::new().add_attribute("method", "try_increment"))
+}*/
+/*
+pub fn try_reset(deps: DepsMut, info: MessageInfo, count: i32) -> Result<Response, ContractError> {
+    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
+        if info.sender != state.owner {
+            return Err(ContractError::Unauthorized {});
+        }
+        state.count = count;
+        Ok(state)
+    })?;
+    Ok(Response::new().add_attribute("method", "reset"))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetCount {} => to_binary(&query_count(deps)?),
+    }
+}
+
+fn query_count(deps: Deps) -> StdResult<CountResponse> {
+    let state = STATE.load(deps.storage)?;
+    Ok(CountResponse { count: state.count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
+    use cosmwasm_std::{coins, from_binary};
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+
+        let msg = InstantiateMsg { count: 17 };
+        let info = mock_info("creator", &coins(1000, "earth"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
+        let value: CountResponse = from_binary(&res).unwrap();
+        assert_eq!(17, value.count);
+    }
+
+    #[test]
+    fn increment() {
+        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+
+        let msg = InstantiateMsg { count: 17 };
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Increment {};
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // should increase counter by 1
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
+        let value: CountResponse = from_binary(&res).unwrap();
+        assert_eq!(18, value.count);
+    }
+
+    #[test]
+    fn reset() {
+        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+
+        let msg = InstantiateMsg { count: 17 };
+        let info = mock_info("creator", &coins(2, "token"));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // beneficiary can release it
+        let unauth_info = mock_info("anyone", &coins(2, "token"));
+        let msg = ExecuteMsg::Reset { count: 5 };
+        let res = execute(deps.as_mut(), mock_env(), unauth_info, msg);
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // only the original creator can reset the counter
+        let auth_info = mock_info("creator", &coins(2, "token"));
+        let msg = ExecuteMsg::Reset { count: 5 };
+        let _res = execute(deps.as_mut(), mock_env(), auth_info, msg).unwrap();
+
+        // should now be 5
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
+        let value: CountResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.count);
+    }
+}
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{CashOutConfig, CommitRevealConfig, VaultCondition, VaultKind, VaultMetadata};
+    use std::str::FromStr;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockStorage};
+    use cosmwasm_std::{
+        coins, from_binary, from_slice, ContractResult, Empty, OwnedDeps, Querier, QuerierResult,
+        QueryRequest, SystemResult, WasmQuery,
+    };
+
+    const DENOM: &str = "uusd";
+
+    fn setup(deps: DepsMut) {
+        let msg = InstantiateMsg {
+            denom: DENOM.to_string(),
+            position_nft_code_id: None,
+            creation_bond: None,
+            resolution_period: None,
+            treasury: None,
+        };
+        instantiate(deps, mock_env(), mock_info("owner", &[]), msg).unwrap();
+    }
+
+    fn deposit(deps: DepsMut, sender: &str, amount: u128) {
+        let info = mock_info(sender, &coins(amount, DENOM));
+        execute(deps, mock_env(), info, ExecuteMsg::BetTokens { referrer: None }).unwrap();
+    }
+
+    fn vault_msg() -> CreateVaultMsg {
+        CreateVaultMsg {
+            description: "will it rain".to_string(),
+            kind: None,
+            outcomes: None,
+            start_height: None,
+            end_height: None,
+            allow_side_switch: None,
+            early_exit_fee: None,
+            min_bet: None,
+            max_bet: None,
+            max_pool: None,
+            allowlist: None,
+            member_group: None,
+            commit_reveal: None,
+            swap_fee: None,
+            cash_out: None,
+            buckets: None,
+            condition: None,
+            metadata: None,
+        }
+    }
+
+    fn create(deps: DepsMut, msg: CreateVaultMsg) -> u64 {
+        let msg = ExecuteMsg::CreateVault(msg);
+        let res = execute(deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let data: CreateVaultResponse = from_binary(&res.data.unwrap()).unwrap();
+        data.vault_id
+    }
+
+    fn bet(deps: DepsMut, sender: &str, vault_id: u64, vote: &str, weight: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::CastBet {
+            vault_id,
+            vote: vote.to_string(),
+            weight: Uint128::new(weight),
+            referrer: None,
+        };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    #[test]
+    fn same_side_bet_tops_up_position() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        bet(deps.as_mut(), "alice", vault_id, "yes", 20).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("alice")]);
+        assert_eq!(a_vault.voter_info.len(), 1);
+        assert_eq!(a_vault.voter_info[0].weight, Uint128::new(50));
+        assert_eq!(a_vault.voter_info[0].pool_pct, Decimal::one());
+        assert_eq!(a_vault.yes_votes, Uint128::new(50));
+        assert_eq!(a_vault.sum_votes, Uint128::new(50));
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(50))]);
+        assert_eq!(token_manager.participated_vaults, vec![vault_id]);
+    }
+
+    #[test]
+    fn top_up_cannot_exceed_balance() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 80).unwrap();
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 21) {
+            Err(ContractError::VaultInsufficientAmt {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", vault_id, "yes", 20).unwrap();
+    }
+
+    #[test]
+    fn withdraw_keeps_stakes_in_every_vault_locked() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let first = create(deps.as_mut(), vault_msg());
+        let second = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", first, "yes", 30).unwrap();
+        bet(deps.as_mut(), "alice", second, "no", 20).unwrap();
+
+        // only locking the largest stake would free 70 and leave the second
+        // vault's stake unbacked
+        let withdraw = |amount| ExecuteMsg::WithdrawRewards { amount: Some(Uint128::new(amount)) };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), withdraw(70)) {
+            Err(ContractError::ExcessiveWithdraw { max_amount }) => assert_eq!(max_amount, Uint128::new(50)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), withdraw(50)).unwrap();
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(50));
+        assert_eq!(bet_amount(b"alice", &deps.storage), Uint128::new(50));
+    }
+
+    #[test]
+    fn side_switch_rejected_by_default() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        match bet(deps.as_mut(), "alice", vault_id, "no", 10) {
+            Err(ContractError::VaultSenderVoted {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voter_info[0].vote, "yes");
+        assert_eq!(a_vault.voter_info[0].weight, Uint128::new(30));
+    }
+
+    #[test]
+    fn side_switch_moves_whole_position_when_allowed() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { allow_side_switch: Some(true), ..vault_msg() },
+        );
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        bet(deps.as_mut(), "alice", vault_id, "no", 10).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voter_info.len(), 1);
+        assert_eq!(a_vault.voter_info[0].vote, "no");
+        assert_eq!(a_vault.voter_info[0].weight, Uint128::new(40));
+        assert_eq!(a_vault.yes_votes, Uint128::zero());
+        assert_eq!(a_vault.no_votes, Uint128::new(40));
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(40))]);
+        assert_eq!(token_manager.participated_vaults, vec![vault_id]);
+    }
+
+    #[test]
+    fn bets_in_several_vaults_share_one_balance() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        create(deps.as_mut(), vault_msg());
+        create(deps.as_mut(), vault_msg());
+
+        bet(deps.as_mut(), "alice", 1, "yes", 60).unwrap();
+        match bet(deps.as_mut(), "alice", 2, "no", 50) {
+            Err(ContractError::VaultInsufficientAmt {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", 2, "no", 40).unwrap();
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.participated_vaults, vec![1, 2]);
+    }
+
+    fn retract(deps: DepsMut, env: Env, sender: &str, vault_id: u64, amount: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::RetractBet {
+            vault_id,
+            amount: Uint128::new(amount),
+        };
+        execute(deps, env, mock_info(sender, &[]), msg)
+    }
+
+    #[test]
+    fn partial_retract_charges_exit_fee() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() },
+        );
+        bet(deps.as_mut(), "alice", vault_id, "yes", 50).unwrap();
+
+        retract(deps.as_mut(), mock_env(), "alice", vault_id, 20).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voter_info[0].weight, Uint128::new(30));
+        assert_eq!(a_vault.yes_votes, Uint128::new(30));
+        assert_eq!(a_vault.exit_fees, Uint128::new(2));
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(98));
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(30))]);
+        assert_eq!(token_manager.participated_vaults, vec![vault_id]);
+    }
+
+    #[test]
+    fn full_retract_removes_position() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 50).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 10).unwrap();
+
+        retract(deps.as_mut(), mock_env(), "alice", vault_id, 50).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("bob")]);
+        assert_eq!(a_vault.voter_info[0].pool_pct, Decimal::one());
+        assert_eq!(a_vault.sum_votes, Uint128::new(10));
+        assert_eq!(a_vault.exit_fees, Uint128::zero());
+
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(100));
+        assert!(token_manager.bet_token.is_empty());
+        assert!(token_manager.participated_vaults.is_empty());
+
+        match retract(deps.as_mut(), mock_env(), "alice", vault_id, 1) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn retract_rejected_over_position_or_after_deadline() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 50).unwrap();
+
+        match retract(deps.as_mut(), mock_env(), "alice", vault_id, 51) {
+            Err(ContractError::ExcessiveRetract { max_amount }) => {
+                assert_eq!(max_amount, Uint128::new(50))
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        match retract(deps.as_mut(), env, "alice", vault_id, 10) {
+            Err(ContractError::VaultDepositPeriodExpired { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn exit_fee_above_one_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg {
+            early_exit_fee: Some(Decimal::percent(101)),
+            ..vault_msg()
+        });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidExitFee {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn bet_limits_enforced() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg {
+                min_bet: Some(Uint128::new(5)),
+                max_bet: Some(Uint128::new(40)),
+                max_pool: Some(Uint128::new(60)),
+                ..vault_msg()
+            },
+        );
+
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 4) {
+            Err(ContractError::BetBelowMinimum { min_bet }) => assert_eq!(min_bet, Uint128::new(5)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 41) {
+            Err(ContractError::BetAboveMaximum { max_bet }) => assert_eq!(max_bet, Uint128::new(40)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        // the per-address cap applies to the whole position, top-ups included
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 11) {
+            Err(ContractError::BetAboveMaximum { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", vault_id, "yes", 10).unwrap();
+
+        match bet(deps.as_mut(), "bob", vault_id, "no", 25) {
+            Err(ContractError::VaultPoolCapReached { max_pool }) => assert_eq!(max_pool, Uint128::new(60)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "bob", vault_id, "no", 20).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.sum_votes, Uint128::new(60));
+    }
+
+    #[test]
+    fn contradicting_bet_limits_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let invalid = vec![
+            CreateVaultMsg { min_bet: Some(Uint128::zero()), ..vault_msg() },
+            CreateVaultMsg {
+                min_bet: Some(Uint128::new(10)),
+                max_bet: Some(Uint128::new(9)),
+                ..vault_msg()
+            },
+            CreateVaultMsg {
+                min_bet: Some(Uint128::new(10)),
+                max_pool: Some(Uint128::new(9)),
+                ..vault_msg()
+            },
+        ];
+        for msg in invalid {
+            let msg = ExecuteMsg::CreateVault(msg);
+            match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+                Err(ContractError::InvalidBetLimits {}) => {}
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn private_vault_admits_only_allowlisted_members() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { allowlist: Some(vec!["alice".to_string()]), ..vault_msg() },
+        );
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 10).unwrap();
+        match bet(deps.as_mut(), "bob", vault_id, "no", 10) {
+            Err(ContractError::VaultNotMember {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // only the creator manages the allowlist
+        let add_bob = ExecuteMsg::AddMembers { vault_id, addresses: vec!["bob".to_string()] };
+        match execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), add_bob.clone()) {
+            Err(ContractError::VaultNotCreator { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), add_bob).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 10).unwrap();
+
+        let remove_alice = ExecuteMsg::RemoveMembers { vault_id, addresses: vec!["alice".to_string()] };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), remove_alice).unwrap();
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::VaultNotMember {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let query_msg = QueryMsg::VaultMember { vault_id, address: "bob".to_string() };
+        let res: VaultMemberResponse = from_binary(&query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert!(res.member);
+    }
+
+    #[test]
+    fn members_cannot_be_added_to_public_vault() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let vault_id = create(deps.as_mut(), vault_msg());
+        let msg = ExecuteMsg::AddMembers { vault_id, addresses: vec!["bob".to_string()] };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultNotPrivate {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    /// GroupQuerier answers raw cw4 membership lookups against the "group" contract
+    struct GroupQuerier {
+        members: Vec<&'static str>,
+    }
+
+    impl Querier for GroupQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            match request {
+                QueryRequest::Wasm(WasmQuery::Raw { contract_addr, key }) if contract_addr == "group" => {
+                    let member = self
+                        .members
+                        .iter()
+                        .any(|m| key.as_slice() == cw4::member_key(m).as_slice());
+                    let value = if member { to_binary(&1u64).unwrap() } else { Binary::default() };
+                    SystemResult::Ok(ContractResult::Ok(value))
+                }
+                _ => panic!("Unexpected query: {:?}", request),
+            }
+        }
+    }
+
+    #[test]
+    fn private_vault_admits_cw4_group_members() {
+        let mut deps = OwnedDeps {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: GroupQuerier { members: vec!["alice"] },
+        };
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { member_group: Some("group".to_string()), ..vault_msg() },
+        );
+
+        bet(deps.as_mut(), "alice", vault_id, "yes", 10).unwrap();
+        match bet(deps.as_mut(), "bob", vault_id, "no", 10) {
+            Err(ContractError::VaultNotMember {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn commit_reveal_vault(deps: DepsMut, refund_unrevealed: bool) -> u64 {
+        let commit_reveal = CommitRevealConfig { reveal_period: 10, refund_unrevealed };
+        create(deps, CreateVaultMsg { commit_reveal: Some(commit_reveal), ..vault_msg() })
+    }
+
+    fn commit(deps: DepsMut, sender: &str, vault_id: u64, vote: &str, salt: &str, weight: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::CommitBet {
+            vault_id,
+            commitment: bet_commitment(sender, vote, salt),
+            weight: Uint128::new(weight),
+        };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn reveal(deps: DepsMut, env: Env, sender: &str, vault_id: u64, vote: &str, salt: &str) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::RevealBet {
+            vault_id,
+            vote: vote.to_string(),
+            salt: salt.to_string(),
+        };
+        execute(deps, env, mock_info(sender, &[]), msg)
+    }
+
+    /// end_deposits_env returns the env after the default deposit period
+    /// together with the reveal deadline EndDeposits sets in it
+    fn end_deposits_env(deps: DepsMut, vault_id: u64) -> (Env, u64) {
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        let msg = ExecuteMsg::EndDeposits { vault_id };
+        execute(deps, env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let reveal_deadline = env.block.height + 10;
+        (env, reveal_deadline)
+    }
+
+    #[test]
+    fn committed_bet_is_hidden_until_revealed() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::VaultRequiresCommitment {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 40).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert!(a_vault.voter_info.is_empty());
+        assert_eq!(a_vault.committed_votes, Uint128::new(40));
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(40))]);
+
+        // nothing can be revealed while deposits are open
+        match reveal(deps.as_mut(), mock_env(), "alice", vault_id, "yes", "pepper") {
+            Err(ContractError::VaultNotInProgress {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let (env, _) = end_deposits_env(deps.as_mut(), vault_id);
+        reveal(deps.as_mut(), env, "alice", vault_id, "yes", "pepper").unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("alice")]);
+        assert_eq!(a_vault.yes_votes, Uint128::new(40));
+        assert_eq!(a_vault.committed_votes, Uint128::zero());
+    }
+
+    #[test]
+    fn wrong_reveal_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 40).unwrap();
+
+        // bob copies alice's commitment, hoping to reveal with her vote and salt
+        let msg = ExecuteMsg::CommitBet {
+            vault_id,
+            commitment: bet_commitment("alice", "yes", "pepper"),
+            weight: Uint128::new(40),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg).unwrap();
+
+        let (env, _) = end_deposits_env(deps.as_mut(), vault_id);
+        for (sender, vote, salt) in [("alice", "no", "pepper"), ("alice", "yes", "salt"), ("bob", "yes", "pepper")] {
+            match reveal(deps.as_mut(), env.clone(), sender, vault_id, vote, salt) {
+                Err(ContractError::CommitmentMismatch {}) => {}
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+        match reveal(deps.as_mut(), env.clone(), "carol", vault_id, "yes", "pepper") {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        reveal(deps.as_mut(), env, "alice", vault_id, "yes", "pepper").unwrap();
+    }
+
+    #[test]
+    fn unrevealed_commitments_forfeited_after_deadline() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 40).unwrap();
+        commit(deps.as_mut(), "bob", vault_id, "no", "paprika", 30).unwrap();
+
+        let (mut env, reveal_deadline) = end_deposits_env(deps.as_mut(), vault_id);
+        reveal(deps.as_mut(), env.clone(), "alice", vault_id, "yes", "pepper").unwrap();
+
+        let expire = ExecuteMsg::ExpireCommitments { vault_id };
+        env.block.height = reveal_deadline;
+        match execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), expire.clone()) {
+            Err(ContractError::VaultRevealPeriodNotExpired { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        env.block.height = reveal_deadline + 1;
+        match reveal(deps.as_mut(), env.clone(), "bob", vault_id, "no", "paprika") {
+            Err(ContractError::VaultRevealPeriodExpired { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), expire).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.committed_votes, Uint128::zero());
+        assert_eq!(a_vault.forfeited, Uint128::new(30));
+        assert_eq!(a_vault.sum_votes, Uint128::new(40));
+
+        let token_manager = bank_read(&deps.storage).load(b"bob").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(70));
+        assert!(token_manager.bet_token.is_empty());
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(40))]);
+    }
+
+    #[test]
+    fn unrevealed_commitments_refunded_when_configured() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), true);
+        commit(deps.as_mut(), "bob", vault_id, "no", "paprika", 30).unwrap();
+
+        let (mut env, reveal_deadline) = end_deposits_env(deps.as_mut(), vault_id);
+        env.block.height = reveal_deadline + 1;
+        let expire = ExecuteMsg::ExpireCommitments { vault_id };
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), expire).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.forfeited, Uint128::zero());
+        let token_manager = bank_read(&deps.storage).load(b"bob").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(100));
+        assert!(token_manager.bet_token.is_empty());
+        assert!(token_manager.participated_vaults.is_empty());
+    }
+
+    fn balance(deps: Deps, address: &str) -> Uint128 {
+        let query_msg = QueryMsg::TokenBet { address: address.to_string() };
+        let res: TokenBetResponse = from_binary(&query(deps, mock_env(), query_msg).unwrap()).unwrap();
+        res.token_balance
+    }
+
+    fn resolve(mut deps: DepsMut, vault_id: u64, outcome: &str) {
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        let msg = ExecuteMsg::EndDeposits { vault_id };
+        execute(deps.branch(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id, outcome: outcome.to_string() };
+        execute(deps, env, mock_info("creator", &[]), msg).unwrap();
+    }
+
+    fn claim(deps: DepsMut, sender: &str, vault_id: u64) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::ClaimWinnings { vault_id };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    #[test]
+    fn parimutuel_winners_split_the_pot() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        deposit(deps.as_mut(), "carol", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { early_exit_fee: Some(Decimal::percent(50)), ..vault_msg() },
+        );
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "yes", 20).unwrap();
+        bet(deps.as_mut(), "carol", vault_id, "no", 40).unwrap();
+        // carol backs out of half her bet, leaving 10 in the pot
+        retract(deps.as_mut(), mock_env(), "carol", vault_id, 20).unwrap();
+
+        match claim(deps.as_mut(), "alice", vault_id) {
+            Err(ContractError::VaultNotOpenForWithdrawals {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        resolve(deps.as_mut(), vault_id, "yes");
+
+        // pot = 60 + 20 + 20 + 10 = 110, split 3:1 between alice and bob
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        claim(deps.as_mut(), "carol", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(122));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(107));
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(70));
+
+        match claim(deps.as_mut(), "alice", vault_id) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let token_manager = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert!(token_manager.bet_token.is_empty());
+    }
+
+    #[test]
+    fn unbacked_result_shares_the_pot_with_every_stake() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let outcomes = vec!["red".to_string(), "green".to_string(), "blue".to_string()];
+        let msg = CreateVaultMsg { outcomes: Some(outcomes), early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() };
+        let vault_id = create(deps.as_mut(), msg);
+        bet(deps.as_mut(), "alice", vault_id, "red", 50).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "green", 30).unwrap();
+        retract(deps.as_mut(), mock_env(), "alice", vault_id, 20).unwrap();
+
+        // nobody backed blue: the pot of 60 + 2 in exit fees is split 1:1
+        resolve(deps.as_mut(), vault_id, "blue");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(99));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(101));
+    }
+
+    #[test]
+    fn empty_pool_fees_go_to_the_treasury() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() },
+        );
+        bet(deps.as_mut(), "alice", vault_id, "yes", 50).unwrap();
+        retract(deps.as_mut(), mock_env(), "alice", vault_id, 50).unwrap();
+
+        resolve(deps.as_mut(), vault_id, "yes");
+        assert_eq!(balance(deps.as_ref(), "owner"), Uint128::new(5));
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(95));
+    }
+
+    #[test]
+    fn bets_must_name_a_vault_outcome() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let outcomes = vec!["red".to_string(), "green".to_string(), "blue".to_string()];
+        let vault_id = create(deps.as_mut(), CreateVaultMsg { outcomes: Some(outcomes), ..vault_msg() });
+
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::InvalidBetEvent {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", vault_id, "blue", 10).unwrap();
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.sum_votes, Uint128::new(10));
+
+        let duplicate = vec!["red".to_string(), "red".to_string()];
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { outcomes: Some(duplicate), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidOutcomes { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn lmsr_vault(deps: DepsMut) -> u64 {
+        let kind = VaultKind::Lmsr { liquidity: Uint128::new(1_000_000) };
+        create(deps, CreateVaultMsg { kind: Some(kind), ..vault_msg() })
+    }
+
+    fn trade(deps: DepsMut, sender: &str, vault_id: u64, outcome: &str, amount: u128, sell: bool) -> Result<Response, ContractError> {
+        let (outcome, shares) = (outcome.to_string(), Uint128::new(amount));
+        let msg = if sell {
+            ExecuteMsg::SellShares { vault_id, outcome, shares, min_proceeds: None }
+        } else {
+            ExecuteMsg::BuyShares { vault_id, outcome, shares, max_cost: None }
+        };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn prices(deps: Deps, vault_id: u64) -> Vec<Decimal> {
+        let res: PricesResponse = from_binary(&query(deps, mock_env(), QueryMsg::Prices { vault_id }).unwrap()).unwrap();
+        res.prices.into_iter().map(|p| p.price).collect()
+    }
+
+    #[test]
+    fn lmsr_creator_funds_the_subsidy() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        match execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::CreateVault(CreateVaultMsg {
+                kind: Some(VaultKind::Lmsr { liquidity: Uint128::new(1_000_000) }),
+                ..vault_msg()
+            }),
+        ) {
+            Err(ContractError::VaultInsufficientAmt {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        let vault_id = lmsr_vault(deps.as_mut());
+        // b * ln(2), rounded up
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(1_000_000 - 693_148));
+        assert_eq!(prices(deps.as_ref(), vault_id), vec![Decimal::percent(50); 2]);
+
+        deposit(deps.as_mut(), "alice", 100);
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn lmsr_out_of_range_trades_are_errors() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        deposit(deps.as_mut(), "alice", 1_000_000);
+        let vault_id = lmsr_vault(deps.as_mut());
+        trade(deps.as_mut(), "alice", vault_id, "yes", 10, false).unwrap();
+
+        match trade(deps.as_mut(), "alice", vault_id, "yes", u128::MAX, false) {
+            Err(ContractError::Std(_)) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        for (shares, sell) in [(Uint128::MAX, false), (Uint128::new(11), true)] {
+            let simulate = QueryMsg::SimulateTrade { vault_id, outcome: "yes".to_string(), shares, sell };
+            assert!(query(deps.as_ref(), mock_env(), simulate).is_err());
+        }
+    }
+
+    #[test]
+    fn lmsr_trades_move_prices_and_respect_limits() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        deposit(deps.as_mut(), "alice", 1_000_000);
+        let vault_id = lmsr_vault(deps.as_mut());
+
+        let simulate = QueryMsg::SimulateTrade {
+            vault_id,
+            outcome: "yes".to_string(),
+            shares: Uint128::new(500_000),
+            sell: false,
+        };
+        let quote: SimulateTradeResponse = from_binary(&query(deps.as_ref(), mock_env(), simulate).unwrap()).unwrap();
+
+        let msg = ExecuteMsg::BuyShares {
+            vault_id,
+            outcome: "yes".to_string(),
+            shares: Uint128::new(500_000),
+            max_cost: Some(quote.amount - Uint128::new(1)),
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::SlippageExceeded { amount }) => assert_eq!(amount, quote.amount),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        trade(deps.as_mut(), "alice", vault_id, "yes", 500_000, false).unwrap();
+
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(1_000_000) - quote.amount);
+        let after = prices(deps.as_ref(), vault_id);
+        assert_eq!(after, quote.prices.iter().map(|p| p.price).collect::<Vec<_>>());
+        assert!(after[0] > Decimal::percent(62) && after[0] < Decimal::percent(63));
+
+        match trade(deps.as_mut(), "alice", vault_id, "yes", 500_001, true) {
+            Err(ContractError::InsufficientShares {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // a round trip never pays out more than it cost
+        trade(deps.as_mut(), "alice", vault_id, "yes", 500_000, true).unwrap();
+        assert!(balance(deps.as_ref(), "alice") <= Uint128::new(1_000_000));
+        assert_eq!(prices(deps.as_ref(), vault_id), vec![Decimal::percent(50); 2]);
+    }
+
+    #[test]
+    fn lmsr_winning_shares_redeem_one_to_one() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        deposit(deps.as_mut(), "alice", 1_000_000);
+        deposit(deps.as_mut(), "bob", 1_000_000);
+        let vault_id = lmsr_vault(deps.as_mut());
+
+        trade(deps.as_mut(), "alice", vault_id, "yes", 800_000, false).unwrap();
+        trade(deps.as_mut(), "bob", vault_id, "no", 300_000, false).unwrap();
+        trade(deps.as_mut(), "bob", vault_id, "yes", 100_000, false).unwrap();
+        let alice_before = balance(deps.as_ref(), "alice");
+        let bob_before = balance(deps.as_ref(), "bob");
+
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        match claim(deps.as_mut(), "bob", vault_id) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        assert_eq!(balance(deps.as_ref(), "alice"), alice_before + Uint128::new(800_000));
+        assert_eq!(balance(deps.as_ref(), "bob"), bob_before + Uint128::new(100_000));
+        // everything the market maker held went to winners or back to the creator
+        let market = lmsr_market_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(market.collateral, Uint128::zero());
+        let total = balance(deps.as_ref(), "creator") + balance(deps.as_ref(), "alice") + balance(deps.as_ref(), "bob");
+        assert_eq!(total, Uint128::new(3_000_000));
+    }
+
+    fn amm_vault(deps: DepsMut) -> u64 {
+        let kind = VaultKind::Amm { liquidity: Uint128::new(1_000) };
+        create(deps, CreateVaultMsg { kind: Some(kind), ..vault_msg() })
+    }
+
+    fn split(deps: DepsMut, sender: &str, vault_id: u64, amount: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::SplitCollateral { vault_id, amount: Uint128::new(amount) };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn swap(deps: DepsMut, sender: &str, vault_id: u64, offer: &str, amount: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::SwapOutcome {
+            vault_id,
+            offer_outcome: offer.to_string(),
+            amount: Uint128::new(amount),
+            min_return: None,
+        };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn holdings(deps: Deps, vault_id: u64, address: &str) -> Vec<Uint128> {
+        shares_read(deps.storage, vault_id).load(address.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn amm_creator_seeds_both_reserves() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        let outcomes = vec!["red".to_string(), "green".to_string(), "blue".to_string()];
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg {
+            kind: Some(VaultKind::Amm { liquidity: Uint128::new(1_000) }),
+            outcomes: Some(outcomes),
+            ..vault_msg()
+        });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidOutcomes { max_outcomes: 2 }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let vault_id = amm_vault(deps.as_mut());
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::zero());
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(pool.reserves, vec![Uint128::new(1_000); 2]);
+        assert_eq!(pool.collateral, Uint128::new(1_000));
+        assert_eq!(prices(deps.as_ref(), vault_id), vec![Decimal::percent(50); 2]);
+    }
+
+    #[test]
+    fn amm_split_and_merge_round_trip() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 500);
+        let vault_id = amm_vault(deps.as_mut());
+
+        match split(deps.as_mut(), "alice", vault_id, 501) {
+            Err(ContractError::VaultInsufficientAmt {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        split(deps.as_mut(), "alice", vault_id, 200).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(300));
+        assert_eq!(holdings(deps.as_ref(), vault_id, "alice"), vec![Uint128::new(200); 2]);
+
+        let msg = ExecuteMsg::MergeOutcomes { vault_id, amount: Uint128::new(201) };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::InsufficientShares {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::MergeOutcomes { vault_id, amount: Uint128::new(200) };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(500));
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(pool.collateral, Uint128::new(1_000));
+    }
+
+    #[test]
+    fn amm_swaps_move_prices_and_allow_early_exit() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 500);
+        let vault_id = amm_vault(deps.as_mut());
+        split(deps.as_mut(), "alice", vault_id, 100).unwrap();
+
+        let simulate = QueryMsg::SimulateSwap {
+            vault_id,
+            offer_outcome: "no".to_string(),
+            amount: Uint128::new(100),
+        };
+        let quote: SimulateSwapResponse = from_binary(&query(deps.as_ref(), mock_env(), simulate).unwrap()).unwrap();
+        assert_eq!(quote.return_amount, Uint128::new(90));
+
+        let msg = ExecuteMsg::SwapOutcome {
+            vault_id,
+            offer_outcome: "no".to_string(),
+            amount: Uint128::new(100),
+            min_return: Some(Uint128::new(91)),
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::SlippageExceeded { amount }) => assert_eq!(amount, Uint128::new(90)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        swap(deps.as_mut(), "alice", vault_id, "no", 100).unwrap();
+        assert_eq!(holdings(deps.as_ref(), vault_id, "alice"), vec![Uint128::new(190), Uint128::zero()]);
+        let after = prices(deps.as_ref(), vault_id);
+        assert_eq!(after, quote.prices.iter().map(|p| p.price).collect::<Vec<_>>());
+        assert!(after[0] > Decimal::percent(50));
+
+        // selling back out of "yes" before resolution recovers most of the stake
+        swap(deps.as_mut(), "alice", vault_id, "yes", 95).unwrap();
+        let held = holdings(deps.as_ref(), vault_id, "alice");
+        let exit = held[0].min(held[1]);
+        let msg = ExecuteMsg::MergeOutcomes { vault_id, amount: exit };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        assert!(balance(deps.as_ref(), "alice") < Uint128::new(500));
+        assert!(balance(deps.as_ref(), "alice") >= Uint128::new(490));
+    }
+
+    #[test]
+    fn amm_resolution_conserves_collateral() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 500);
+        deposit(deps.as_mut(), "bob", 500);
+        let vault_id = amm_vault(deps.as_mut());
+
+        split(deps.as_mut(), "alice", vault_id, 300).unwrap();
+        swap(deps.as_mut(), "alice", vault_id, "no", 300).unwrap();
+        split(deps.as_mut(), "bob", vault_id, 200).unwrap();
+        swap(deps.as_mut(), "bob", vault_id, "yes", 200).unwrap();
+        let alice_yes = holdings(deps.as_ref(), vault_id, "alice")[0];
+
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        withdraw(deps.as_mut(), "creator", vault_id, 1_000).unwrap();
+
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(200) + alice_yes);
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(pool.collateral, Uint128::zero());
+        let total = balance(deps.as_ref(), "creator") + balance(deps.as_ref(), "alice") + balance(deps.as_ref(), "bob");
+        assert_eq!(total, Uint128::new(2_000));
+    }
+
+    fn withdraw(deps: DepsMut, sender: &str, vault_id: u64, shares: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::WithdrawLiquidity { vault_id, shares: Uint128::new(shares) };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn provide(deps: DepsMut, sender: &str, vault_id: u64, amount: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::ProvideLiquidity { vault_id, amount: Uint128::new(amount) };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn lp_position(deps: Deps, vault_id: u64, address: &str) -> LpSharesResponse {
+        let msg = QueryMsg::LpShares { vault_id, address: address.to_string() };
+        from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn liquidity_is_added_at_current_prices() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 500);
+        deposit(deps.as_mut(), "carol", 1_100);
+        let vault_id = amm_vault(deps.as_mut());
+        assert_eq!(lp_position(deps.as_ref(), vault_id, "creator").shares, Uint128::new(1_000));
+
+        split(deps.as_mut(), "alice", vault_id, 100).unwrap();
+        swap(deps.as_mut(), "alice", vault_id, "no", 100).unwrap();
+        let before = prices(deps.as_ref(), vault_id);
+
+        // reserves are now [910, 1100]
+        provide(deps.as_mut(), "carol", vault_id, 1_100).unwrap();
+        let pool: PoolResponse = from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Pool { vault_id }).unwrap()).unwrap();
+        assert_eq!(pool.reserves[0].shares, Uint128::new(1_820));
+        assert_eq!(pool.reserves[1].shares, Uint128::new(2_200));
+        assert_eq!(pool.collateral, Uint128::new(2_200));
+        assert_eq!(prices(deps.as_ref(), vault_id), before);
+        assert_eq!(holdings(deps.as_ref(), vault_id, "carol"), vec![Uint128::new(190), Uint128::zero()]);
+        let position = lp_position(deps.as_ref(), vault_id, "carol");
+        assert_eq!(position.shares, Uint128::new(1_000));
+        assert_eq!(position.total_lp_shares, Uint128::new(2_000));
+
+        match withdraw(deps.as_mut(), "carol", vault_id, 1_001) {
+            Err(ContractError::InsufficientLpShares {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        withdraw(deps.as_mut(), "carol", vault_id, 1_000).unwrap();
+        assert_eq!(holdings(deps.as_ref(), vault_id, "carol"), vec![Uint128::new(1_100); 2]);
+        assert_eq!(lp_position(deps.as_ref(), vault_id, "carol").shares, Uint128::zero());
+    }
+
+    #[test]
+    fn swap_fees_accrue_to_liquidity_providers() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 1_000);
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg {
+            kind: Some(VaultKind::Amm { liquidity: Uint128::new(1_000) }),
+            swap_fee: Some(Decimal::one()),
+            ..vault_msg()
+        });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidSwapFee {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg {
+                kind: Some(VaultKind::Amm { liquidity: Uint128::new(1_000) }),
+                swap_fee: Some(Decimal::percent(10)),
+                ..vault_msg()
+            },
+        );
+
+        split(deps.as_mut(), "alice", vault_id, 500).unwrap();
+        // 100 offered, 10 kept as a fee: 1000 * 1000 / 1090 -> pool keeps 918
+        swap(deps.as_mut(), "alice", vault_id, "no", 100).unwrap();
+        assert_eq!(holdings(deps.as_ref(), vault_id, "alice"), vec![Uint128::new(582), Uint128::new(400)]);
+        let msg = ExecuteMsg::MergeOutcomes { vault_id, amount: Uint128::new(400) };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+
+        // the provider's units now exceed the complete sets it started with
+        withdraw(deps.as_mut(), "creator", vault_id, 1_000).unwrap();
+        let held = holdings(deps.as_ref(), vault_id, "creator");
+        assert_eq!(held, vec![Uint128::new(918), Uint128::new(1_100)]);
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(pool.reserves, vec![Uint128::zero(); 2]);
+        assert_eq!(pool.total_lp_shares, Uint128::zero());
+    }
+
+    #[test]
+    fn liquidity_withdrawals_never_touch_bettor_collateral() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "carol", 700);
+        deposit(deps.as_mut(), "alice", 500);
+        deposit(deps.as_mut(), "bob", 500);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg {
+                kind: Some(VaultKind::Amm { liquidity: Uint128::new(1_000) }),
+                swap_fee: Some(Decimal::percent(3)),
+                ..vault_msg()
+            },
+        );
+        provide(deps.as_mut(), "carol", vault_id, 700).unwrap();
+        split(deps.as_mut(), "alice", vault_id, 400).unwrap();
+        swap(deps.as_mut(), "alice", vault_id, "no", 400).unwrap();
+        split(deps.as_mut(), "bob", vault_id, 300).unwrap();
+        swap(deps.as_mut(), "bob", vault_id, "yes", 150).unwrap();
+
+        // every outcome's units held outside the pool stay fully backed
+        let backed = |deps: Deps| {
+            let pool = amm_pool_read(deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+            for i in 0..2 {
+                let held: Uint128 = ["creator", "carol", "alice", "bob"]
+                    .iter()
+                    .filter_map(|a| shares_read(deps.storage, vault_id).may_load(a.as_bytes()).unwrap())
+                    .map(|h| h[i])
+                    .sum();
+                assert_eq!(held + pool.reserves[i], pool.collateral);
+            }
+        };
+        backed(deps.as_ref());
+        withdraw(deps.as_mut(), "carol", vault_id, 300).unwrap();
+        backed(deps.as_ref());
+
+        resolve(deps.as_mut(), vault_id, "no");
+        withdraw(deps.as_mut(), "creator", vault_id, 1_000).unwrap();
+        let carol_left = lp_position(deps.as_ref(), vault_id, "carol").shares;
+        withdraw(deps.as_mut(), "carol", vault_id, carol_left.u128()).unwrap();
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        let bettors_no: Uint128 = ["carol", "alice", "bob"]
+            .iter()
+            .map(|a| holdings(deps.as_ref(), vault_id, a)[1])
+            .sum();
+        assert_eq!(pool.collateral, bettors_no);
+
+        for address in ["carol", "alice", "bob"] {
+            claim(deps.as_mut(), address, vault_id).unwrap();
+        }
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(pool.collateral, Uint128::zero());
+        let total: Uint128 = ["creator", "carol", "alice", "bob"]
+            .iter()
+            .map(|a| balance(deps.as_ref(), a))
+            .sum();
+        assert_eq!(total, Uint128::new(2_700));
+    }
+
+    fn cash_out_vault(deps: DepsMut, exit_fees_fund_reserve: bool) -> u64 {
+        let cash_out = CashOutConfig { discount: Decimal::percent(10), exit_fees_fund_reserve };
+        create(deps, CreateVaultMsg { cash_out: Some(cash_out), ..vault_msg() })
+    }
+
+    fn quote(deps: Deps, vault_id: u64, address: &str) -> CashOutQuoteResponse {
+        let msg = QueryMsg::CashOutQuote { vault_id, address: address.to_string() };
+        from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
+    }
+
+    fn fund_reserve(deps: DepsMut, vault_id: u64, amount: u128) {
+        let msg = ExecuteMsg::FundCashOutReserve { vault_id, amount: Uint128::new(amount) };
+        execute(deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn cash_out_pays_discounted_value_from_reserve() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 100);
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = cash_out_vault(deps.as_mut(), false);
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 40).unwrap();
+
+        let offer = quote(deps.as_ref(), vault_id, "alice");
+        assert_eq!(offer.position, Uint128::new(60));
+        assert_eq!(offer.value, Uint128::new(60));
+        assert_eq!(offer.offer, Uint128::new(54));
+        let msg = ExecuteMsg::CashOut { vault_id };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg.clone()) {
+            Err(ContractError::CashOutReserveInsufficient { available }) => assert!(available.is_zero()),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        fund_reserve(deps.as_mut(), vault_id, 100);
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg.clone()).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(94));
+        // the lock is gone along with the position
+        assert_eq!(free_balance(&deps.storage, &Addr::unchecked("alice")).unwrap(), Uint128::new(94));
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("bob")]);
+        assert_eq!(a_vault.reserve_positions, vec![("yes".to_string(), Uint128::new(60))]);
+        assert_eq!(a_vault.yes_votes, Uint128::new(60));
+        assert_eq!(a_vault.sum_votes, Uint128::new(100));
+        assert_eq!(a_vault.cash_out_reserve, Uint128::new(46));
+
+        // the reserve's positions and leftovers go back to the creator
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(146));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(60));
+    }
+
+    #[test]
+    fn cash_out_requires_an_enabled_vault() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 100);
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::CashOut { vault_id }) {
+            Err(ContractError::VaultCashOutDisabled {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::FundCashOutReserve { vault_id, amount: Uint128::new(10) };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultCashOutDisabled {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn exit_fees_can_fund_the_cash_out_reserve() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg {
+                early_exit_fee: Some(Decimal::percent(50)),
+                cash_out: Some(CashOutConfig { discount: Decimal::zero(), exit_fees_fund_reserve: true }),
+                ..vault_msg()
+            },
+        );
+        bet(deps.as_mut(), "alice", vault_id, "yes", 40).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 40).unwrap();
+        retract(deps.as_mut(), mock_env(), "alice", vault_id, 20).unwrap();
+        retract(deps.as_mut(), mock_env(), "bob", vault_id, 20).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.cash_out_reserve, Uint128::new(20));
+        assert_eq!(a_vault.exit_fees, Uint128::zero());
+        // bob's remaining 20 sell at their full value, paid for by the fees
+        let msg = ExecuteMsg::CashOut { vault_id };
+        execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg).unwrap();
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(90));
+    }
+
+    /// NftQuerier answers cw721 ownership lookups against the "nft" contract
+    struct NftQuerier {
+        owner: &'static str,
+    }
+
+    impl Querier for NftQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            match request {
+                QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) if contract_addr == "nft" => {
+                    match from_binary(&msg).unwrap() {
+                        Cw721QueryMsg::OwnerOf { .. } => {
+                            let owner = OwnerOfResponse { owner: self.owner.to_string(), approvals: vec![] };
+                            SystemResult::Ok(ContractResult::Ok(to_binary(&owner).unwrap()))
+                        }
+                        msg => panic!("Unexpected query: {:?}", msg),
+                    }
+                }
+                _ => panic!("Unexpected query: {:?}", request),
+            }
+        }
+    }
+
+    fn nft_reply(deps: DepsMut, address: &str) {
+        let event = cosmwasm_std::Event::new("instantiate").add_attribute("_contract_address", address);
+        let msg = Reply {
+            id: INSTANTIATE_POSITION_NFT_REPLY_ID,
+            result: ContractResult::Ok(cosmwasm_std::SubMsgExecutionResponse { events: vec![event], data: None }),
+        };
+        reply(deps, mock_env(), msg).unwrap();
+    }
+
+    #[test]
+    fn migrate_records_contract_version() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let version = cw2::get_contract_version(&deps.storage).unwrap();
+        assert_eq!(version.contract, CONTRACT_NAME);
+
+        cw2::set_contract_version(&mut deps.storage, CONTRACT_NAME, "0.0.1").unwrap();
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+        assert_eq!(res.events[0].ty, "cowbet_migrate");
+        let version = cw2::get_contract_version(&deps.storage).unwrap();
+        assert_eq!(version.version, CONTRACT_VERSION);
+    }
+
+    #[test]
+    fn instantiate_creates_position_nft_contract() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg {
+            denom: DENOM.to_string(),
+            position_nft_code_id: Some(7),
+            creation_bond: None,
+            resolution_period: None,
+            treasury: None,
+        };
+        let res = instantiate(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, INSTANTIATE_POSITION_NFT_REPLY_ID);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate { code_id, msg, .. }) => {
+                assert_eq!(*code_id, 7);
+                let nft_msg: Cw721InstantiateMsg = from_binary(msg).unwrap();
+                assert_eq!(nft_msg.minter, mock_env().contract.address.to_string());
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        nft_reply(deps.as_mut(), "nft");
+        assert_eq!(config_read(&deps.storage).load().unwrap().position_nft, Some(Addr::unchecked("nft")));
+    }
+
+    #[test]
+    fn receipt_holder_claims_the_position() {
+        let mut deps = OwnedDeps {
+            storage: MockStorage::default(),
+            api: MockApi::default(),
+            querier: NftQuerier { owner: "carol" },
+        };
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 40).unwrap();
+
+        let msg = ExecuteMsg::MintReceipt { vault_id };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg.clone()) {
+            Err(ContractError::PositionNftNotConfigured {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        nft_reply(deps.as_mut(), "nft");
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg.clone()).unwrap();
+        let token_id = format!("{}:alice", vault_id);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, msg, .. }) => {
+                assert_eq!(contract_addr, "nft");
+                match from_binary(msg).unwrap() {
+                    Cw721ExecuteMsg::Mint(mint) => {
+                        assert_eq!(mint.token_id, token_id);
+                        assert_eq!(mint.owner, "alice");
+                    }
+                    msg => panic!("Unexpected message: {:?}", msg),
+                }
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::PositionHasReceipt { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // the position is frozen while its receipt is out
+        match retract(deps.as_mut(), mock_env(), "alice", vault_id, 10) {
+            Err(ContractError::PositionHasReceipt { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        resolve(deps.as_mut(), vault_id, "yes");
+        match claim(deps.as_mut(), "alice", vault_id) {
+            Err(ContractError::PositionHasReceipt { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::ClaimReceipt { token_id: token_id.clone() };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg.clone()) {
+            Err(ContractError::NotReceiptOwner { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), msg.clone()).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(100));
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(40));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(60));
+        match execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), msg) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn transfer(deps: DepsMut, sender: &str, vault_id: u64, recipient: &str, amount: u128) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::TransferPosition {
+            vault_id,
+            recipient: recipient.to_string(),
+            amount: Uint128::new(amount),
+        };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    #[test]
+    fn transfer_moves_stake_and_lock() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 40).unwrap();
+
+        match transfer(deps.as_mut(), "alice", vault_id, "carol", 61) {
+            Err(ContractError::ExcessiveTransfer { max_amount }) => assert_eq!(max_amount, Uint128::new(60)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        match transfer(deps.as_mut(), "alice", vault_id, "bob", 10) {
+            Err(ContractError::RecipientVotedOtherSide {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        transfer(deps.as_mut(), "alice", vault_id, "carol", 20).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("alice"), Addr::unchecked("bob"), Addr::unchecked("carol")]);
+        assert_eq!(a_vault.voter_info[0].weight, Uint128::new(40));
+        assert_eq!(a_vault.voter_info[2].weight, Uint128::new(20));
+        assert_eq!(a_vault.voter_info[2].vote, "yes");
+        assert_eq!(a_vault.yes_votes, Uint128::new(60));
+        let carol = bank_read(&deps.storage).load(b"carol").unwrap();
+        assert_eq!(carol.token_balance, Uint128::new(20));
+        assert_eq!(carol.bet_token, vec![(vault_id, Uint128::new(20))]);
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(80));
+        assert_eq!(free_balance(&deps.storage, &Addr::unchecked("alice")).unwrap(), Uint128::new(40));
+
+        // moving the rest closes the sender's position
+        transfer(deps.as_mut(), "alice", vault_id, "carol", 40).unwrap();
+        let alice = bank_read(&deps.storage).load(b"alice").unwrap();
+        assert!(alice.bet_token.is_empty());
+        assert!(alice.participated_vaults.is_empty());
+
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "carol", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(100));
+        match claim(deps.as_mut(), "alice", vault_id) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn private_vault_positions_move_only_to_members() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(
+            deps.as_mut(),
+            CreateVaultMsg { allowlist: Some(vec!["alice".to_string(), "bob".to_string()]), ..vault_msg() },
+        );
+        bet(deps.as_mut(), "alice", vault_id, "yes", 60).unwrap();
+
+        match transfer(deps.as_mut(), "alice", vault_id, "carol", 20) {
+            Err(ContractError::VaultNotMember {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        transfer(deps.as_mut(), "alice", vault_id, "bob", 20).unwrap();
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.voters, vec![Addr::unchecked("alice"), Addr::unchecked("bob")]);
+    }
+
+    #[test]
+    fn positions_do_not_move_onto_unrevealed_commitments() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 50).unwrap();
+        commit(deps.as_mut(), "bob", vault_id, "yes", "salt", 10).unwrap();
+        let (mut env, reveal_deadline) = end_deposits_env(deps.as_mut(), vault_id);
+        reveal(deps.as_mut(), env.clone(), "alice", vault_id, "yes", "pepper").unwrap();
+
+        match transfer(deps.as_mut(), "alice", vault_id, "bob", 50) {
+            Err(ContractError::RecipientHasCommitment {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        env.block.height = reveal_deadline + 1;
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::ExpireCommitments { vault_id }).unwrap();
+        transfer(deps.as_mut(), "alice", vault_id, "bob", 50).unwrap();
+        let token_manager = bank_read(&deps.storage).load(b"bob").unwrap();
+        assert_eq!(token_manager.token_balance, Uint128::new(140));
+        assert_eq!(token_manager.bet_token, vec![(vault_id, Uint128::new(50))]);
+        let msg = ExecuteMsg::WithdrawRewards { amount: Some(Uint128::new(91)) };
+        match execute(deps.as_mut(), mock_env(), mock_info("bob", &[]), msg) {
+            Err(ContractError::ExcessiveWithdraw { max_amount }) => assert_eq!(max_amount, Uint128::new(90)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn scalar_vault(deps: DepsMut) -> u64 {
+        let kind = VaultKind::Scalar { lower: Decimal::from_str("100").unwrap(), upper: Decimal::from_str("200").unwrap() };
+        create(deps, CreateVaultMsg { kind: Some(kind), ..vault_msg() })
+    }
+
+    fn resolve_scalar(mut deps: DepsMut, vault_id: u64, value: &str) -> Result<Response, ContractError> {
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        let msg = ExecuteMsg::EndDeposits { vault_id };
+        execute(deps.branch(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let msg = ExecuteMsg::ResolveWithValue { vault_id, value: Decimal::from_str(value).unwrap() };
+        execute(deps, env, mock_info("creator", &[]), msg)
+    }
+
+    #[test]
+    fn scalar_pot_splits_linearly_within_bounds() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        deposit(deps.as_mut(), "carol", 100);
+        let vault_id = scalar_vault(deps.as_mut());
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::InvalidBetEvent {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        bet(deps.as_mut(), "alice", vault_id, "long", 40).unwrap();
+        bet(deps.as_mut(), "carol", vault_id, "long", 20).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "short", 40).unwrap();
+
+        // 175 is three quarters of the way from 100 to 200
+        resolve_scalar(deps.as_mut(), vault_id, "175").unwrap();
+        for address in ["alice", "bob", "carol"] {
+            claim(deps.as_mut(), address, vault_id).unwrap();
+        }
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(110));
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(105));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(85));
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.resolved_value, Some(Decimal::from_str("175").unwrap()));
+    }
+
+    #[test]
+    fn scalar_payout_clamps_at_bounds() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = scalar_vault(deps.as_mut());
+        bet(deps.as_mut(), "alice", vault_id, "long", 60).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "short", 40).unwrap();
+
+        resolve_scalar(deps.as_mut(), vault_id, "50").unwrap();
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(40));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(160));
+    }
+
+    #[test]
+    fn scalar_vaults_resolve_only_on_a_value() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let kind = VaultKind::Scalar { lower: Decimal::percent(5), upper: Decimal::percent(5) };
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { kind: Some(kind), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidScalarBounds {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let binary = create(deps.as_mut(), vault_msg());
+        match resolve_scalar(deps.as_mut(), binary, "150") {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let vault_id = scalar_vault(deps.as_mut());
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::EndDeposits { vault_id }).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id, outcome: "long".to_string() };
+        match execute(deps.as_mut(), env, mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn bucket_vault(deps: DepsMut) -> u64 {
+        let buckets = ["0", "10", "20", "30"].iter().map(|b| Decimal::from_str(b).unwrap()).collect();
+        create(deps, CreateVaultMsg { buckets: Some(buckets), ..vault_msg() })
+    }
+
+    #[test]
+    fn buckets_become_outcomes() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let vault_id = bucket_vault(deps.as_mut());
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.outcomes, vec!["0-10", "10-20", "20-30"]);
+
+        let unordered = vec![Decimal::percent(200), Decimal::percent(100), Decimal::percent(300)];
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { buckets: Some(unordered), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidBuckets { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn bucketed_vault_resolves_to_the_bucket_holding_the_value() {
+        assert_eq!(bucket_index(&[Decimal::zero(), Decimal::percent(50), Decimal::one()], Decimal::percent(50)), 1);
+
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = bucket_vault(deps.as_mut());
+        bet(deps.as_mut(), "alice", vault_id, "10-20", 30).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "20-30", 30).unwrap();
+
+        // outcome names cannot be used to resolve, the value decides
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::EndDeposits { vault_id }).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id, outcome: "20-30".to_string() };
+        match execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::ResolveWithValue { vault_id, value: Decimal::from_str("12.5").unwrap() };
+        execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.result, "10-20");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(130));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(70));
+    }
+
+    #[test]
+    fn cancelled_vault_refunds_every_stake() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(deps.as_mut(), CreateVaultMsg { early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() });
+        bet(deps.as_mut(), "alice", vault_id, "yes", 50).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 40).unwrap();
+        retract(deps.as_mut(), mock_env(), "bob", vault_id, 20).unwrap();
+
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), ExecuteMsg::EndVault { vault_id }) {
+            Err(ContractError::VaultNotCreator { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ExecuteMsg::EndVault { vault_id }).unwrap();
+        match bet(deps.as_mut(), "alice", vault_id, "yes", 10) {
+            Err(ContractError::VaultNotOpenForDeposits {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(100));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(98));
+        // the exit fee has no pot left to go to
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(2));
+
+        deposit(deps.as_mut(), "creator", 1_000);
+        let amm_id = amm_vault(deps.as_mut());
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ExecuteMsg::EndVault { vault_id: amm_id }) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn parlay_bet(deps: DepsMut, sender: &str, legs: &[(u64, &str)], stake: u128) -> Result<Response, ContractError> {
+        let legs = legs
+            .iter()
+            .map(|(vault_id, outcome)| ParlayLegMsg { vault_id: *vault_id, outcome: outcome.to_string() })
+            .collect();
+        let msg = ExecuteMsg::PlaceParlay { legs, stake: Uint128::new(stake), min_payout: None };
+        execute(deps, mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn settle(deps: DepsMut, parlay_id: u64) -> Result<Response, ContractError> {
+        execute(deps, mock_env(), mock_info("anyone", &[]), ExecuteMsg::SettleParlay { parlay_id })
+    }
+
+    fn parlay_reserve(deps: Deps) -> Uint128 {
+        config_read(deps.storage).load().unwrap().parlay_reserve
+    }
+
+    // a parimutuel vault whose bets cost something to take back
+    fn parlay_vault(deps: DepsMut) -> u64 {
+        create(deps, CreateVaultMsg { early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() })
+    }
+
+    // two vaults where "yes" is implied at 75% and 50%, and a funded reserve
+    fn parlay_setup(deps: DepsMut) -> (u64, u64) {
+        let mut deps = deps;
+        setup(deps.branch());
+        deposit(deps.branch(), "owner", 100);
+        deposit(deps.branch(), "alice", 100);
+        deposit(deps.branch(), "bob", 100);
+        deposit(deps.branch(), "carol", 30);
+        let msg = ExecuteMsg::FundParlayReserve { amount: Uint128::new(100) };
+        execute(deps.branch(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        let first = parlay_vault(deps.branch());
+        bet(deps.branch(), "alice", first, "yes", 30).unwrap();
+        bet(deps.branch(), "bob", first, "no", 10).unwrap();
+        let second = parlay_vault(deps.branch());
+        bet(deps.branch(), "alice", second, "yes", 20).unwrap();
+        bet(deps.branch(), "bob", second, "no", 20).unwrap();
+        (first, second)
+    }
+
+    #[test]
+    fn parlay_pays_the_product_of_the_odds() {
+        let mut deps = mock_dependencies(&[]);
+        let (first, second) = parlay_setup(deps.as_mut());
+
+        let res = parlay_bet(deps.as_mut(), "carol", &[(first, "yes"), (second, "yes")], 30).unwrap();
+        let data: PlaceParlayResponse = from_binary(&res.data.unwrap()).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Parlay { parlay_id: data.parlay_id }).unwrap();
+        let value: ParlayResponse = from_binary(&res).unwrap();
+        // 30 / 0.75 / 0.5
+        assert_eq!(value.payout, Uint128::new(80));
+        assert_eq!(value.legs[0].probability, Decimal::percent(75));
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::zero());
+        assert_eq!(parlay_reserve(deps.as_ref()), Uint128::new(50));
+
+        resolve(deps.as_mut(), first, "yes");
+        match settle(deps.as_mut(), data.parlay_id) {
+            Err(ContractError::ParlayNotSettled {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        resolve(deps.as_mut(), second, "yes");
+        settle(deps.as_mut(), data.parlay_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(80));
+        assert_eq!(parlay_reserve(deps.as_ref()), Uint128::new(50));
+        match settle(deps.as_mut(), data.parlay_id) {
+            Err(ContractError::ParlaySettled {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn parlay_loses_as_soon_as_one_leg_does() {
+        let mut deps = mock_dependencies(&[]);
+        let (first, second) = parlay_setup(deps.as_mut());
+        parlay_bet(deps.as_mut(), "carol", &[(first, "yes"), (second, "yes")], 30).unwrap();
+
+        resolve(deps.as_mut(), first, "no");
+        settle(deps.as_mut(), 1).unwrap();
+        let a_parlay = parlay_read(&deps.storage).load(&1u64.to_be_bytes()).unwrap();
+        assert_eq!(a_parlay.status, ParlayStatus::Lost);
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::zero());
+        assert_eq!(parlay_reserve(deps.as_ref()), Uint128::new(130));
+
+        let msg = ExecuteMsg::WithdrawParlayReserve { amount: Uint128::new(130) };
+        match execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), msg.clone()) {
+            Err(ContractError::Unauthorized {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("owner", &[]), msg).unwrap();
+        assert_eq!(balance(deps.as_ref(), "owner"), Uint128::new(130));
+    }
+
+    #[test]
+    fn void_legs_drop_out_of_the_odds() {
+        let mut deps = mock_dependencies(&[]);
+        let (first, second) = parlay_setup(deps.as_mut());
+        parlay_bet(deps.as_mut(), "carol", &[(first, "yes"), (second, "yes")], 30).unwrap();
+
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ExecuteMsg::EndVault { vault_id: second }).unwrap();
+        resolve(deps.as_mut(), first, "yes");
+        settle(deps.as_mut(), 1).unwrap();
+        // only the first leg's odds are left: 30 / 0.75
+        assert_eq!(balance(deps.as_ref(), "carol"), Uint128::new(40));
+        assert_eq!(parlay_reserve(deps.as_ref()), Uint128::new(90));
+    }
+
+    #[test]
+    fn parlay_needs_priced_distinct_legs_and_reserve() {
+        let mut deps = mock_dependencies(&[]);
+        let (first, second) = parlay_setup(deps.as_mut());
+        let empty = parlay_vault(deps.as_mut());
+
+        for legs in [vec![(first, "yes")], vec![(first, "yes"), (first, "no")]] {
+            match parlay_bet(deps.as_mut(), "carol", &legs, 10) {
+                Err(ContractError::InvalidParlay { max_legs }) => assert_eq!(max_legs, MAX_PARLAY_LEGS),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+        match parlay_bet(deps.as_mut(), "carol", &[(first, "yes"), (empty, "yes")], 10) {
+            Err(ContractError::ParlayLegUnpriced { vault_id }) => assert_eq!(vault_id, empty),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // long odds: 30 / 0.25 / 0.5 = 240 would take 210 from the reserve
+        match parlay_bet(deps.as_mut(), "carol", &[(first, "no"), (second, "no")], 30) {
+            Err(ContractError::ParlayReserveInsufficient { available }) => assert_eq!(available, Uint128::new(100)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let legs = vec![
+            ParlayLegMsg { vault_id: first, outcome: "yes".to_string() },
+            ParlayLegMsg { vault_id: second, outcome: "yes".to_string() },
+        ];
+        let msg = ExecuteMsg::PlaceParlay { legs, stake: Uint128::new(30), min_payout: Some(Uint128::new(81)) };
+        match execute(deps.as_mut(), mock_env(), mock_info("carol", &[]), msg) {
+            Err(ContractError::SlippageExceeded { amount }) => assert_eq!(amount, Uint128::new(80)),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn parlay_legs_need_odds_that_cost_to_move() {
+        let mut deps = mock_dependencies(&[]);
+        let (first, _) = parlay_setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        let free_exit = create(deps.as_mut(), vault_msg());
+        let switching = create(
+            deps.as_mut(),
+            CreateVaultMsg { allow_side_switch: Some(true), early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() },
+        );
+        for vault_id in [free_exit, switching] {
+            bet(deps.as_mut(), "alice", vault_id, "yes", 10).unwrap();
+            bet(deps.as_mut(), "bob", vault_id, "no", 10).unwrap();
+        }
+        let lmsr_id = lmsr_vault(deps.as_mut());
+        let amm_id = amm_vault(deps.as_mut());
+
+        for leg in [free_exit, switching, lmsr_id, amm_id] {
+            match parlay_bet(deps.as_mut(), "carol", &[(first, "yes"), (leg, "yes")], 10) {
+                Err(ContractError::ParlayLegUnwindable { vault_id }) => assert_eq!(vault_id, leg),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn parlay_odds_are_capped() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let whale = 10u128.pow(36);
+        deposit(deps.as_mut(), "alice", 10);
+        deposit(deps.as_mut(), "bob", 1_000);
+        deposit(deps.as_mut(), "carol", whale);
+        let long = parlay_vault(deps.as_mut());
+        let longer = parlay_vault(deps.as_mut());
+        let longest = parlay_vault(deps.as_mut());
+        for (vault_id, no) in [(long, 99), (longer, 99), (longest, 200)] {
+            bet(deps.as_mut(), "alice", vault_id, "yes", 1).unwrap();
+            bet(deps.as_mut(), "bob", vault_id, "no", no).unwrap();
+        }
+
+        match parlay_bet(deps.as_mut(), "carol", &[(long, "yes"), (longest, "yes")], 1) {
+            Err(ContractError::ParlayLegOddsTooLong { vault_id, max_odds }) => {
+                assert_eq!((vault_id, max_odds), (longest, MAX_LEG_ODDS))
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // 10^36 at 100 to 1 twice does not fit a Uint128
+        match parlay_bet(deps.as_mut(), "carol", &[(long, "yes"), (longer, "yes")], whale) {
+            Err(ContractError::Std(StdError::ConversionOverflow { .. })) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn conditional_vault(deps: DepsMut, parent_id: u64, outcome: &str) -> u64 {
+        let condition = VaultCondition { vault_id: parent_id, outcome: outcome.to_string() };
+        create(deps, CreateVaultMsg { condition: Some(condition), ..vault_msg() })
+    }
+
+    #[test]
+    fn conditional_vault_cancelled_when_parent_resolves_otherwise() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let parent_id = create(deps.as_mut(), vault_msg());
+        let child_id = conditional_vault(deps.as_mut(), parent_id, "yes");
+        let a_parent = vault_read(&deps.storage).load(&parent_id.to_be_bytes()).unwrap();
+        assert_eq!(a_parent.conditional_vaults, vec![child_id]);
+        bet(deps.as_mut(), "alice", child_id, "yes", 40).unwrap();
+        bet(deps.as_mut(), "bob", child_id, "no", 20).unwrap();
+
+        resolve(deps.as_mut(), parent_id, "no");
+        let a_child = vault_read(&deps.storage).load(&child_id.to_be_bytes()).unwrap();
+        assert_eq!(a_child.status, VaultStatus::Closed);
+        claim(deps.as_mut(), "alice", child_id).unwrap();
+        claim(deps.as_mut(), "bob", child_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(100));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(100));
+    }
+
+    #[test]
+    fn conditional_vault_resolves_once_parent_meets_condition() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let parent_id = create(deps.as_mut(), vault_msg());
+        let child_id = conditional_vault(deps.as_mut(), parent_id, "yes");
+        bet(deps.as_mut(), "alice", child_id, "yes", 40).unwrap();
+        bet(deps.as_mut(), "bob", child_id, "no", 20).unwrap();
+
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        let msg = ExecuteMsg::EndDeposits { vault_id: child_id };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id: child_id, outcome: "yes".to_string() };
+        match execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg.clone()) {
+            Err(ContractError::VaultConditionPending {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        resolve(deps.as_mut(), parent_id, "yes");
+        execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        claim(deps.as_mut(), "alice", child_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(120));
+    }
+
+    #[test]
+    fn conditions_must_name_an_unresolved_parent_outcome() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let parent_id = create(deps.as_mut(), vault_msg());
+        let condition = VaultCondition { vault_id: parent_id, outcome: "maybe".to_string() };
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { condition: Some(condition), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidCondition {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let condition = VaultCondition { vault_id: parent_id + 1, outcome: "yes".to_string() };
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { condition: Some(condition), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultDoesNotExist {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let condition = VaultCondition { vault_id: parent_id, outcome: "yes".to_string() };
+        let kind = VaultKind::Lmsr { liquidity: Uint128::new(1_000_000) };
+        let msg = ExecuteMsg::CreateVault(CreateVaultMsg { kind: Some(kind), condition: Some(condition), ..vault_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    fn template_msg() -> CreateTemplateMsg {
+        CreateTemplateMsg {
+            description: "week {n} rain".to_string(),
+            kind: None,
+            outcomes: None,
+            buckets: None,
+            duration: Some(100),
+            allow_side_switch: None,
+            early_exit_fee: None,
+            min_bet: None,
+            max_bet: None,
+            max_pool: None,
+            metadata: None,
+        }
+    }
+
+    fn series_setup(deps: DepsMut) -> u64 {
+        let mut deps = deps;
+        setup(deps.branch());
+        let msg = ExecuteMsg::CreateTemplate(template_msg());
+        execute(deps.branch(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let msg = ExecuteMsg::CreateSeries { template_id: 1, interval: 1_000, count: 2 };
+        let res = execute(deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let data: CreateSeriesResponse = from_binary(&res.data.unwrap()).unwrap();
+        data.series_id
+    }
+
+    #[test]
+    fn series_spawns_vaults_on_schedule() {
+        let mut deps = mock_dependencies(&[]);
+        let series_id = series_setup(deps.as_mut());
+        let height = mock_env().block.height;
+        let first = vault_read(&deps.storage).load(&1u64.to_be_bytes()).unwrap();
+        assert_eq!(first.creator, Addr::unchecked("creator"));
+        assert_eq!(first.description, "week 1 rain");
+        assert_eq!(first.end_height, height + 100);
+        assert_eq!(first.series_id, Some(series_id));
+
+        let msg = ExecuteMsg::SpawnSeriesVault { series_id };
+        match execute(deps.as_mut(), mock_env(), mock_info("anyone", &[]), msg.clone()) {
+            Err(ContractError::SeriesVaultNotDue { spawn_height }) => assert_eq!(spawn_height, height + 100),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let mut env = mock_env();
+        env.block.height += 100;
+        let res = execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg.clone()).unwrap();
+        let data: CreateVaultResponse = from_binary(&res.data.unwrap()).unwrap();
+        let second = vault_read(&deps.storage).load(&data.vault_id.to_be_bytes()).unwrap();
+        assert_eq!(second.creator, Addr::unchecked("creator"));
+        assert_eq!(second.end_height, height + 1_100);
+        assert_eq!(second.start_height, Some(height + 1_000));
+
+        env.block.height += 2_000;
+        match execute(deps.as_mut(), env, mock_info("anyone", &[]), msg) {
+            Err(ContractError::SeriesComplete {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Series { series_id }).unwrap();
+        let value: VaultSeries = from_binary(&res).unwrap();
+        assert_eq!(value.vault_ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn late_spawn_moves_to_the_next_deadline() {
+        let mut deps = mock_dependencies(&[]);
+        let series_id = series_setup(deps.as_mut());
+        let mut env = mock_env();
+        env.block.height += 100 + 2_500;
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::SpawnSeriesVault { series_id }).unwrap();
+        let second = vault_read(&deps.storage).load(&2u64.to_be_bytes()).unwrap();
+        assert_eq!(second.end_height, mock_env().block.height + 100 + 3_000);
+        assert_eq!(second.start_height, Some(mock_env().block.height + 3_000));
+    }
+
+    #[test]
+    fn only_template_creator_starts_pooled_series() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let kind = VaultKind::Lmsr { liquidity: Uint128::new(1_000_000) };
+        let msg = ExecuteMsg::CreateTemplate(CreateTemplateMsg { kind: Some(kind), ..template_msg() });
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultKindMismatch {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::CreateTemplate(template_msg());
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::CreateSeries { template_id: 1, interval: 1_000, count: 2 };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let msg = ExecuteMsg::CreateSeries { template_id: 1, interval: 0, count: 2 };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::InvalidSeries {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
         }
-        QueryMsg::Vault { vault_id } => query_vault(deps, vault_id),
     }
-}
 
-fn query_vault(deps: Deps, vault_id: u64) -> StdResult<Binary> {
-    let key = &vault_id.to_be_bytes();
+    fn tagged_vault(deps: DepsMut, category: &str, tags: &[&str]) -> u64 {
+        let metadata = VaultMetadata {
+            category: Some(category.to_string()),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..VaultMetadata::default()
+        };
+        create(deps, CreateVaultMsg { metadata: Some(metadata), ..vault_msg() })
+    }
 
-    let vault = match vault_read(deps.storage).may_load(key)? {
-        Some(vault) => Some(vault),
-        None => return Err(StdError::generic_err("Vault does not exist")),
+    fn listed(deps: Deps, msg: QueryMsg) -> Vec<u64> {
+        let res: VaultListResponse = from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+        res.vault_ids
     }
-    .unwrap();
 
-    let resp = VaultResponse {
-        creator: vault.creator.to_string(),
-        status: vault.status,
-        end_height: Some(vault.end_height),
-        start_height: vault.start_height,
-        description: vault.description,
-    };
-    to_binary(&resp)
-}
+    #[test]
+    fn vault_keeps_description_and_metadata() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let metadata = VaultMetadata {
+            category: Some("weather".to_string()),
+            tags: vec!["rain".to_string()],
+            resolution_source: Some("https://weather.example/berlin".to_string()),
+            rules_hash: Some(Binary::from(Sha256::digest(b"rules").as_slice())),
+            image_uri: Some("ipfs://rain.png".to_string()),
+        };
+        let vault_id = create(deps.as_mut(), CreateVaultMsg { metadata: Some(metadata.clone()), ..vault_msg() });
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Vault { vault_id }).unwrap();
+        let value: VaultResponse = from_binary(&res).unwrap();
+        assert_eq!(value.description, "will it rain");
+        assert_eq!(value.metadata, Some(metadata));
+    }
 
-fn token_balance(deps: Deps, address: Addr) -> StdResult<Binary> {
-    let token_manager = bank_read(deps.storage)
-        .may_load(address.as_str().as_bytes())?
-        .unwrap_or_default();
+    #[test]
+    fn vaults_listed_by_category_and_tag() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        tagged_vault(deps.as_mut(), "weather", &["rain"]);
+        tagged_vault(deps.as_mut(), "sports", &["football"]);
+        tagged_vault(deps.as_mut(), "weather", &["rain", "wind"]);
 
-    let resp = TokenBetResponse {
-        token_balance: token_manager.token_balance,
-    };
+        let by_category = |start_after, limit| QueryMsg::VaultsByCategory { category: "weather".to_string(), start_after, limit };
+        assert_eq!(listed(deps.as_ref(), by_category(None, None)), vec![1, 3]);
+        assert_eq!(listed(deps.as_ref(), by_category(Some(1), None)), vec![3]);
+        assert_eq!(listed(deps.as_ref(), by_category(None, Some(1))), vec![1]);
+        assert!(listed(deps.as_ref(), by_category(Some(u64::MAX), None)).is_empty());
+        let by_tag = QueryMsg::VaultsByTag { tag: "wind".to_string(), start_after: None, limit: None };
+        assert_eq!(listed(deps.as_ref(), by_tag), vec![3]);
+    }
 
-    to_binary(&resp)
-}
+    #[test]
+    fn invalid_metadata_rejected() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let invalid = [
+            VaultMetadata { tags: vec!["rain".to_string(), "rain".to_string()], ..VaultMetadata::default() },
+            VaultMetadata { category: Some(String::new()), ..VaultMetadata::default() },
+            VaultMetadata { rules_hash: Some(Binary::from(b"not a hash".to_vec())), ..VaultMetadata::default() },
+        ];
+        for metadata in invalid {
+            let msg = ExecuteMsg::CreateVault(CreateVaultMsg { metadata: Some(metadata), ..vault_msg() });
+            match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+                Err(ContractError::InvalidMetadata { max_tags, .. }) => assert_eq!(max_tags, MAX_TAGS),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+    }
 
-/*pub fn try_increment(deps: DepsMut) -> Result<Response, ContractError> {
-    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
-        state.count += 1;
-        Ok(state)
-    })?;
+    fn require_bond(deps: DepsMut, amount: u128) {
+        let msg = ExecuteMsg::UpdateConfig {
+            creation_bond: Some(Uint128::new(amount)),
+            resolution_period: None,
+            dispute_period: None,
+            treasury: Some("treasury".to_string()),
+            protocol_fee: None,
+            referral_share: None,
+        };
+        execute(deps, mock_env(), mock_info("owner", &[]), msg).unwrap();
+    }
 
-    Ok(Response::new().add_attribute("method", "try_increment"))
-}*/
-/*
-pub fn try_reset(deps: DepsMut, info: MessageInfo, count: i32) -> Result<Response, ContractError> {
-    STATE.update(deps.storage, |mut state| -> Result<_, ContractError> {
-        if info.sender != state.owner {
-            return Err(ContractError::Unauthorized {});
+    #[test]
+    fn creation_bond_returned_on_resolution() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        let msg = ExecuteMsg::CreateVault(vault_msg());
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultInsufficientAmt {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
         }
-        state.count = count;
-        Ok(state)
-    })?;
-    Ok(Response::new().add_attribute("method", "reset"))
-}
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetCount {} => to_binary(&query_count(deps)?),
+        deposit(deps.as_mut(), "creator", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(50));
+        resolve(deps.as_mut(), vault_id, "yes");
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(100));
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.bond, Uint128::zero());
     }
-}
 
-fn query_count(deps: Deps) -> StdResult<CountResponse> {
-    let state = STATE.load(deps.storage)?;
-    Ok(CountResponse { count: state.count })
-}
+    #[test]
+    fn unresolved_vault_expires_and_slashes_bond() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        deposit(deps.as_mut(), "creator", 100);
+        deposit(deps.as_mut(), "alice", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 40).unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary};
+        let resolve_deadline = mock_env().block.height + DEFAULT_END_HEIGHT_BLOCKS + DEFAULT_RESOLUTION_PERIOD_BLOCKS;
+        let mut env = mock_env();
+        env.block.height = resolve_deadline;
+        let msg = ExecuteMsg::ExpireVault { vault_id };
+        match execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), msg.clone()) {
+            Err(ContractError::VaultResolutionPeriodNotExpired { resolve_deadline: deadline }) => {
+                assert_eq!(deadline, resolve_deadline)
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        env.block.height += 1;
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::new(50));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(50));
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(100));
+    }
 
     #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+    fn expiring_sends_forfeited_commitments_to_the_treasury() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 40).unwrap();
+        commit(deps.as_mut(), "bob", vault_id, "no", "paprika", 30).unwrap();
+        let (mut env, reveal_deadline) = end_deposits_env(deps.as_mut(), vault_id);
+        reveal(deps.as_mut(), env.clone(), "alice", vault_id, "yes", "pepper").unwrap();
+        env.block.height = reveal_deadline + 1;
+        execute(deps.as_mut(), env.clone(), mock_info("anyone", &[]), ExecuteMsg::ExpireCommitments { vault_id }).unwrap();
 
-        let msg = InstantiateMsg { count: 17 };
-        let info = mock_info("creator", &coins(1000, "earth"));
+        env.block.height = reveal_deadline + DEFAULT_RESOLUTION_PERIOD_BLOCKS + 1;
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::ExpireVault { vault_id }).unwrap();
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        // the creator let the vault expire, so bob's forfeited stake is not theirs
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(100));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(70));
+        assert_eq!(balance(deps.as_ref(), "owner"), Uint128::new(30));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::zero());
+        let total: Uint128 = ["alice", "bob", "owner"].iter().map(|a| balance(deps.as_ref(), a)).sum();
+        assert_eq!(total, config_read(&deps.storage).load().unwrap().bet_tokens);
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.forfeited, Uint128::zero());
+    }
 
-        // it worked, let's query the state
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
-        let value: CountResponse = from_binary(&res).unwrap();
-        assert_eq!(17, value.count);
+    #[test]
+    fn exit_fees_go_to_the_creator_only_when_they_cancel() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        let msg = CreateVaultMsg { early_exit_fee: Some(Decimal::percent(10)), ..vault_msg() };
+        let ended = create(deps.as_mut(), msg.clone());
+        let expired = create(deps.as_mut(), msg);
+        for vault_id in [ended, expired] {
+            bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+            retract(deps.as_mut(), mock_env(), "alice", vault_id, 20).unwrap();
+        }
+
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ExecuteMsg::EndVault { vault_id: ended }).unwrap();
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(2));
+        expire(deps.as_mut(), expired).unwrap();
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(2));
+        assert_eq!(balance(deps.as_ref(), "owner"), Uint128::new(2));
     }
 
     #[test]
-    fn increment() {
-        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+    fn late_resolution_slashes_bond() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        deposit(deps.as_mut(), "creator", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
 
-        let msg = InstantiateMsg { count: 17 };
-        let info = mock_info("creator", &coins(2, "token"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS + DEFAULT_RESOLUTION_PERIOD_BLOCKS + 1;
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::EndDeposits { vault_id }).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id, outcome: "yes".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(event_attrs(&res, "resolve")[5..7], attrs(&[("bond_returned", "0"), ("slashed", "50")]));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::new(50));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(50));
+    }
 
-        // beneficiary can release it
-        let info = mock_info("anyone", &coins(2, "token"));
-        let msg = ExecuteMsg::Increment {};
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    fn expire(deps: DepsMut, vault_id: u64) -> Result<Response, ContractError> {
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS + DEFAULT_RESOLUTION_PERIOD_BLOCKS + 1;
+        execute(deps, env, mock_info("anyone", &[]), ExecuteMsg::ExpireVault { vault_id })
+    }
 
-        // should increase counter by 1
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
-        let value: CountResponse = from_binary(&res).unwrap();
-        assert_eq!(18, value.count);
+    #[test]
+    fn expired_lmsr_vault_is_voided() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        deposit(deps.as_mut(), "creator", 1_000_050);
+        deposit(deps.as_mut(), "alice", 1_000_000);
+        deposit(deps.as_mut(), "bob", 1_000_000);
+        let vault_id = lmsr_vault(deps.as_mut());
+        trade(deps.as_mut(), "alice", vault_id, "yes", 800_000, false).unwrap();
+        trade(deps.as_mut(), "bob", vault_id, "no", 300_000, false).unwrap();
+        let alice_before = balance(deps.as_ref(), "alice");
+        let bob_before = balance(deps.as_ref(), "bob");
+
+        expire(deps.as_mut(), vault_id).unwrap();
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.status, VaultStatus::Closed);
+        let msg = ExecuteMsg::ResolveVault { vault_id, outcome: "yes".to_string() };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::VaultNotInProgress {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        match expire(deps.as_mut(), vault_id) {
+            Err(ContractError::VaultNotInProgress {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // every share redeems for half a complete set
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), alice_before + Uint128::new(400_000));
+        assert_eq!(balance(deps.as_ref(), "bob"), bob_before + Uint128::new(150_000));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::new(50));
+        let market = lmsr_market_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(market.collateral, Uint128::zero());
+        let total: Uint128 = ["creator", "alice", "bob", "treasury"].iter().map(|a| balance(deps.as_ref(), a)).sum();
+        assert_eq!(total, Uint128::new(3_000_050));
     }
 
     #[test]
-    fn reset() {
-        let mut deps = mock_dependencies_with_balance(&coins(2, "token"));
+    fn expired_amm_vault_is_voided() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000);
+        deposit(deps.as_mut(), "alice", 500);
+        let vault_id = amm_vault(deps.as_mut());
+        split(deps.as_mut(), "alice", vault_id, 300).unwrap();
+        swap(deps.as_mut(), "alice", vault_id, "no", 300).unwrap();
+        let alice_units: Uint128 = holdings(deps.as_ref(), vault_id, "alice").iter().copied().sum();
 
-        let msg = InstantiateMsg { count: 17 };
-        let info = mock_info("creator", &coins(2, "token"));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+        expire(deps.as_mut(), vault_id).unwrap();
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        withdraw(deps.as_mut(), "creator", vault_id, 1_000).unwrap();
 
-        // beneficiary can release it
-        let unauth_info = mock_info("anyone", &coins(2, "token"));
-        let msg = ExecuteMsg::Reset { count: 5 };
-        let res = execute(deps.as_mut(), mock_env(), unauth_info, msg);
-        match res {
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(200) + alice_units.multiply_ratio(1u128, 2u128));
+        let pool = amm_pool_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        let total = balance(deps.as_ref(), "creator") + balance(deps.as_ref(), "alice") + pool.collateral;
+        assert_eq!(total, Uint128::new(1_500));
+        assert!(pool.collateral <= Uint128::new(1));
+    }
+
+    #[test]
+    fn expired_market_cancels_its_conditional_vaults() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        deposit(deps.as_mut(), "alice", 100);
+        let parent_id = lmsr_vault(deps.as_mut());
+        let child_id = conditional_vault(deps.as_mut(), parent_id, "yes");
+        bet(deps.as_mut(), "alice", child_id, "yes", 40).unwrap();
+
+        expire(deps.as_mut(), parent_id).unwrap();
+        let a_child = vault_read(&deps.storage).load(&child_id.to_be_bytes()).unwrap();
+        assert_eq!(a_child.status, VaultStatus::Closed);
+        claim(deps.as_mut(), "alice", child_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(100));
+    }
+
+    #[test]
+    fn conditional_vault_deadline_waits_for_its_parent() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        deposit(deps.as_mut(), "creator", 100);
+        let parent_id = create(deps.as_mut(), vault_msg());
+        let child_id = conditional_vault(deps.as_mut(), parent_id, "yes");
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        for vault_id in [parent_id, child_id] {
+            let msg = ExecuteMsg::EndDeposits { vault_id };
+            execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        }
+        match expire(deps.as_mut(), child_id) {
+            Err(ContractError::VaultResolutionPeriodNotExpired { resolve_deadline }) => assert_eq!(resolve_deadline, u64::MAX),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // the parent is resolved on the last block of its period, and the
+        // child gets a full period of its own after that
+        env.block.height += DEFAULT_RESOLUTION_PERIOD_BLOCKS;
+        let msg = ExecuteMsg::ResolveVault { vault_id: parent_id, outcome: "yes".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        match expire(deps.as_mut(), child_id) {
+            Err(ContractError::VaultResolutionPeriodNotExpired { resolve_deadline }) => {
+                assert_eq!(resolve_deadline, env.block.height + DEFAULT_RESOLUTION_PERIOD_BLOCKS)
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        env.block.height += DEFAULT_RESOLUTION_PERIOD_BLOCKS;
+        let msg = ExecuteMsg::ResolveVault { vault_id: child_id, outcome: "yes".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(event_attrs(&res, "resolve")[4..6], attrs(&[("on_time", "true"), ("bond_returned", "50")]));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::zero());
+    }
+
+    fn require_dispute_period(deps: DepsMut, blocks: u64) {
+        let msg = ExecuteMsg::UpdateConfig {
+            creation_bond: None,
+            resolution_period: None,
+            dispute_period: Some(blocks),
+            treasury: None,
+            protocol_fee: None,
+            referral_share: None,
+        };
+        execute(deps, mock_env(), mock_info("owner", &[]), msg).unwrap();
+    }
+
+    fn at_height(deps: DepsMut, sender: &str, blocks_after_end: u64, msg: ExecuteMsg) -> Result<Response, ContractError> {
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS + blocks_after_end;
+        execute(deps, env, mock_info(sender, &[]), msg)
+    }
+
+    fn disputed_setup(deps: DepsMut) -> u64 {
+        let mut deps = deps;
+        setup(deps.branch());
+        require_bond(deps.branch(), 50);
+        require_dispute_period(deps.branch(), 10);
+        deposit(deps.branch(), "creator", 50);
+        deposit(deps.branch(), "alice", 100);
+        deposit(deps.branch(), "bob", 100);
+        let vault_id = create(deps.branch(), vault_msg());
+        bet(deps.branch(), "alice", vault_id, "yes", 40).unwrap();
+        bet(deps.branch(), "bob", vault_id, "no", 20).unwrap();
+        resolve(deps, vault_id, "yes");
+        vault_id
+    }
+
+    #[test]
+    fn overturned_result_slashes_the_bond() {
+        let mut deps = mock_dependencies(&[]);
+        let vault_id = disputed_setup(deps.as_mut());
+        match claim(deps.as_mut(), "alice", vault_id) {
+            Err(ContractError::VaultResultNotFinal {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        deposit(deps.as_mut(), "carol", 10);
+        match at_height(deps.as_mut(), "carol", 5, ExecuteMsg::DisputeResult { vault_id }) {
+            Err(ContractError::VaultNoBet {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        at_height(deps.as_mut(), "bob", 5, ExecuteMsg::DisputeResult { vault_id }).unwrap();
+        match at_height(deps.as_mut(), "alice", 5, ExecuteMsg::DisputeResult { vault_id }) {
+            Err(ContractError::VaultDisputed {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // an open dispute holds the result for another dispute period
+        match at_height(deps.as_mut(), "anyone", 11, ExecuteMsg::FinalizeVault { vault_id }) {
+            Err(ContractError::VaultDisputePeriodNotExpired { dispute_deadline }) => {
+                assert_eq!(dispute_deadline, mock_env().block.height + DEFAULT_END_HEIGHT_BLOCKS + 20)
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let msg = ExecuteMsg::SettleDispute { vault_id, outcome: Some("no".to_string()), value: None };
+        match at_height(deps.as_mut(), "creator", 12, msg.clone()) {
             Err(ContractError::Unauthorized {}) => {}
-            _ => panic!("Must return unauthorized error"),
+            res => panic!("Unexpected result: {:?}", res),
         }
+        let res = at_height(deps.as_mut(), "owner", 12, msg).unwrap();
+        assert_eq!(
+            event_attrs(&res, "settle_dispute")[1..5],
+            attrs(&[("outcome", "no"), ("overturned", "true"), ("bond_returned", "0"), ("slashed", "50")])
+        );
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.dispute, Some(DisputeStatus::Overturned));
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(60));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::new(140));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::new(50));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::zero());
+    }
 
-        // only the original creator can reset the counter
-        let auth_info = mock_info("creator", &coins(2, "token"));
-        let msg = ExecuteMsg::Reset { count: 5 };
-        let _res = execute(deps.as_mut(), mock_env(), auth_info, msg).unwrap();
+    #[test]
+    fn undisputed_result_is_final_after_the_dispute_period() {
+        let mut deps = mock_dependencies(&[]);
+        let vault_id = disputed_setup(deps.as_mut());
+        match at_height(deps.as_mut(), "anyone", 10, ExecuteMsg::FinalizeVault { vault_id }) {
+            Err(ContractError::VaultDisputePeriodNotExpired { dispute_deadline }) => {
+                assert_eq!(dispute_deadline, mock_env().block.height + DEFAULT_END_HEIGHT_BLOCKS + 10)
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        match at_height(deps.as_mut(), "bob", 11, ExecuteMsg::DisputeResult { vault_id }) {
+            Err(ContractError::VaultDisputePeriodExpired { .. }) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let res = at_height(deps.as_mut(), "anyone", 11, ExecuteMsg::FinalizeVault { vault_id }).unwrap();
+        assert_eq!(event_attrs(&res, "finalize_vault")[2..4], attrs(&[("bond_returned", "50"), ("creator_payout", "50")]));
+        match at_height(deps.as_mut(), "anyone", 12, ExecuteMsg::FinalizeVault { vault_id }) {
+            Err(ContractError::VaultNotInDisputePeriod {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(120));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(50));
+    }
 
-        // should now be 5
-        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetCount {}).unwrap();
-        let value: CountResponse = from_binary(&res).unwrap();
-        assert_eq!(5, value.count);
+    #[test]
+    fn upheld_or_lapsed_dispute_keeps_the_result() {
+        let mut deps = mock_dependencies(&[]);
+        let vault_id = disputed_setup(deps.as_mut());
+        at_height(deps.as_mut(), "bob", 0, ExecuteMsg::DisputeResult { vault_id }).unwrap();
+        let msg = ExecuteMsg::SettleDispute { vault_id, outcome: Some("yes".to_string()), value: None };
+        let res = at_height(deps.as_mut(), "owner", 1, msg).unwrap();
+        assert_eq!(
+            event_attrs(&res, "settle_dispute")[2..5],
+            attrs(&[("overturned", "false"), ("bond_returned", "50"), ("slashed", "0")])
+        );
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(120));
+
+        // nobody rules on the second dispute, so it lapses
+        deposit(deps.as_mut(), "creator", 50);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 40).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 20).unwrap();
+        resolve(deps.as_mut(), vault_id, "yes");
+        at_height(deps.as_mut(), "bob", 0, ExecuteMsg::DisputeResult { vault_id }).unwrap();
+        at_height(deps.as_mut(), "anyone", 21, ExecuteMsg::FinalizeVault { vault_id }).unwrap();
+        let a_vault = vault_read(&deps.storage).load(&vault_id.to_be_bytes()).unwrap();
+        assert_eq!(a_vault.dispute, Some(DisputeStatus::Upheld));
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(140));
+        assert_eq!(balance(deps.as_ref(), "creator"), Uint128::new(100));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::zero());
+    }
+
+    #[test]
+    fn conditional_vault_waits_for_a_final_parent() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_dispute_period(deps.as_mut(), 10);
+        deposit(deps.as_mut(), "alice", 100);
+        let parent_id = create(deps.as_mut(), vault_msg());
+        let child_id = conditional_vault(deps.as_mut(), parent_id, "no");
+        bet(deps.as_mut(), "alice", parent_id, "yes", 40).unwrap();
+        bet(deps.as_mut(), "alice", child_id, "yes", 20).unwrap();
+        resolve(deps.as_mut(), parent_id, "yes");
+        at_height(deps.as_mut(), "creator", 0, ExecuteMsg::EndDeposits { vault_id: child_id }).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id: child_id, outcome: "yes".to_string() };
+        match at_height(deps.as_mut(), "creator", 0, msg) {
+            Err(ContractError::VaultConditionPending {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // the result is overturned to the child's condition, so it stays open
+        at_height(deps.as_mut(), "alice", 1, ExecuteMsg::DisputeResult { vault_id: parent_id }).unwrap();
+        let msg = ExecuteMsg::SettleDispute { vault_id: parent_id, outcome: Some("no".to_string()), value: None };
+        at_height(deps.as_mut(), "owner", 2, msg).unwrap();
+        let a_child = vault_read(&deps.storage).load(&child_id.to_be_bytes()).unwrap();
+        assert_eq!(a_child.status, VaultStatus::InProgress);
+        let msg = ExecuteMsg::ResolveVault { vault_id: child_id, outcome: "yes".to_string() };
+        at_height(deps.as_mut(), "creator", 3, msg).unwrap();
+    }
+
+    #[test]
+    fn only_owner_updates_config() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let msg = ExecuteMsg::UpdateConfig {
+            creation_bond: Some(Uint128::new(1)),
+            resolution_period: None,
+            dispute_period: None,
+            treasury: None,
+            protocol_fee: None,
+            referral_share: None,
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg) {
+            Err(ContractError::Unauthorized {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn creator_stats_follow_the_vault_lifecycle() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let on_time = create(deps.as_mut(), vault_msg());
+        let cancelled = create(deps.as_mut(), vault_msg());
+        let late = create(deps.as_mut(), vault_msg());
+        let expired = create(deps.as_mut(), vault_msg());
+
+        resolve(deps.as_mut(), on_time, "yes");
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), ExecuteMsg::EndVault { vault_id: cancelled }).unwrap();
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS + DEFAULT_RESOLUTION_PERIOD_BLOCKS + 1;
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), ExecuteMsg::EndDeposits { vault_id: late }).unwrap();
+        let msg = ExecuteMsg::ResolveVault { vault_id: late, outcome: "no".to_string() };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        execute(deps.as_mut(), env, mock_info("anyone", &[]), ExecuteMsg::ExpireVault { vault_id: expired }).unwrap();
+
+        let msg = QueryMsg::CreatorStats { address: "creator".to_string() };
+        let stats: CreatorStats = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            stats,
+            CreatorStats {
+                vaults_created: 4,
+                resolved_on_time: 1,
+                resolved_late: 1,
+                cancelled: 1,
+                disputed: 0,
+                overturned: 0,
+                expired: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn creator_stats_count_disputes() {
+        let mut deps = mock_dependencies(&[]);
+        let overturned = disputed_setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 50);
+        let upheld = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "bob", upheld, "no", 20).unwrap();
+        resolve(deps.as_mut(), upheld, "yes");
+
+        for (vault_id, outcome) in [(overturned, "no"), (upheld, "yes")] {
+            at_height(deps.as_mut(), "bob", 0, ExecuteMsg::DisputeResult { vault_id }).unwrap();
+            let msg = ExecuteMsg::SettleDispute { vault_id, outcome: Some(outcome.to_string()), value: None };
+            at_height(deps.as_mut(), "owner", 1, msg).unwrap();
+        }
+        let msg = QueryMsg::CreatorStats { address: "creator".to_string() };
+        let stats: CreatorStats = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!((stats.resolved_on_time, stats.disputed, stats.overturned), (2, 2, 1));
+    }
+
+    fn leaderboard_page(deps: Deps, metric: LeaderboardMetric, start_after: Option<&str>, limit: Option<u32>) -> Vec<String> {
+        let msg = QueryMsg::Leaderboard { metric, start_after: start_after.map(|a| a.to_string()), limit };
+        let res: LeaderboardResponse = from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap();
+        res.entries.into_iter().map(|entry| entry.address).collect()
+    }
+
+    #[test]
+    fn leaderboard_ranks_settled_bettors() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        for bettor in ["alice", "bob", "carol"] {
+            deposit(deps.as_mut(), bettor, 100);
+        }
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 10).unwrap();
+        bet(deps.as_mut(), "carol", vault_id, "yes", 20).unwrap();
+        resolve(deps.as_mut(), vault_id, "yes");
+        for bettor in ["alice", "bob", "carol"] {
+            claim(deps.as_mut(), bettor, vault_id).unwrap();
+        }
+
+        let msg = QueryMsg::BettorStats { address: "bob".to_string() };
+        let stats: BettorStatsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(stats.total_wagered, Uint128::new(10));
+        assert_eq!(stats.net_pnl, "-10");
+        assert_eq!(stats.vaults_participated, 1);
+        let msg = QueryMsg::BettorStats { address: "alice".to_string() };
+        let stats: BettorStatsResponse = from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(stats.total_won, Uint128::new(36));
+        assert_eq!(stats.net_pnl, "6");
+        assert_eq!(stats.wins, 1);
+
+        let net = LeaderboardMetric::NetPnl;
+        assert_eq!(leaderboard_page(deps.as_ref(), net, None, None), vec!["alice", "carol", "bob"]);
+        assert_eq!(leaderboard_page(deps.as_ref(), net, Some("alice"), Some(1)), vec!["carol"]);
+        let wins = leaderboard_page(deps.as_ref(), LeaderboardMetric::Wins, None, None);
+        assert_eq!(wins, vec!["carol", "alice", "bob"]);
+    }
+
+    fn bettor_stats(deps: Deps, address: &str) -> BettorStatsResponse {
+        let msg = QueryMsg::BettorStats { address: address.to_string() };
+        from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn bettor_stats_count_stakes_when_placed() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "creator", 1_000_000);
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        deposit(deps.as_mut(), "carol", 100_000);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 30).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 10).unwrap();
+        assert_eq!(bettor_stats(deps.as_ref(), "alice").total_wagered, Uint128::new(30));
+        assert_eq!(bettor_stats(deps.as_ref(), "alice").vaults_participated, 0);
+
+        // the stake stays with alice, the payout goes to whoever claims it
+        transfer(deps.as_mut(), "alice", vault_id, "dave", 10).unwrap();
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "dave", vault_id).unwrap();
+        let alice = bettor_stats(deps.as_ref(), "alice");
+        assert_eq!((alice.total_wagered, alice.total_won, alice.wins), (Uint128::new(30), Uint128::new(26), 1));
+        let dave = bettor_stats(deps.as_ref(), "dave");
+        assert_eq!((dave.total_wagered, dave.total_won, dave.wins), (Uint128::zero(), Uint128::new(13), 1));
+
+        let market_id = lmsr_vault(deps.as_mut());
+        trade(deps.as_mut(), "carol", market_id, "yes", 100_000, false).unwrap();
+        let cost = Uint128::new(100_000) - balance(deps.as_ref(), "carol");
+        assert_eq!(bettor_stats(deps.as_ref(), "carol").total_wagered, cost);
+        resolve(deps.as_mut(), market_id, "yes");
+        claim(deps.as_mut(), "carol", market_id).unwrap();
+        let carol = bettor_stats(deps.as_ref(), "carol");
+        assert_eq!((carol.total_won, carol.wins, carol.vaults_participated), (Uint128::new(100_000), 1, 1));
+        assert_eq!(carol.net_pnl, (Uint128::new(100_000) - cost).to_string());
+    }
+
+    fn referral(deps: Deps, address: &str) -> ReferralResponse {
+        let msg = QueryMsg::Referral { address: address.to_string() };
+        from_binary(&query(deps, mock_env(), msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn referrer_is_stored_once() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let msg = ExecuteMsg::BetTokens { referrer: Some("alice".to_string()) };
+        match execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(10, DENOM)), msg) {
+            Err(ContractError::InvalidReferrer {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        let msg = ExecuteMsg::BetTokens { referrer: Some("bob".to_string()) };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(10, DENOM)), msg).unwrap();
+        let vault_id = create(deps.as_mut(), vault_msg());
+        let msg = ExecuteMsg::CastBet {
+            vault_id,
+            vote: "yes".to_string(),
+            weight: Uint128::new(5),
+            referrer: Some("carol".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(referral(deps.as_ref(), "alice").referrer, Some("bob".to_string()));
+        assert_eq!(referral(deps.as_ref(), "carol").referrer, None);
+    }
+
+    #[test]
+    fn only_new_users_take_a_referrer() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 10);
+        let msg = ExecuteMsg::BetTokens { referrer: Some("bob".to_string()) };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(10, DENOM)), msg).unwrap();
+        let vault_id = create(deps.as_mut(), vault_msg());
+        let msg = ExecuteMsg::CastBet {
+            vault_id,
+            vote: "yes".to_string(),
+            weight: Uint128::new(5),
+            referrer: Some("bob".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(referral(deps.as_ref(), "alice").referrer, None);
+    }
+
+    fn set_protocol_fee(deps: DepsMut, fee: u64, share: u64) -> Result<Response, ContractError> {
+        let msg = ExecuteMsg::UpdateConfig {
+            creation_bond: None,
+            resolution_period: None,
+            dispute_period: None,
+            treasury: Some("treasury".to_string()),
+            protocol_fee: Some(Decimal::percent(fee)),
+            referral_share: Some(Decimal::percent(share)),
+        };
+        execute(deps, mock_env(), mock_info("owner", &[]), msg)
+    }
+
+    #[test]
+    fn referrer_earns_share_of_protocol_fee() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        match set_protocol_fee(deps.as_mut(), 10, 150) {
+            Err(ContractError::InvalidProtocolFee {}) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        set_protocol_fee(deps.as_mut(), 10, 40).unwrap();
+
+        let msg = ExecuteMsg::BetTokens { referrer: Some("referrer".to_string()) };
+        execute(deps.as_mut(), mock_env(), mock_info("alice", &coins(100, DENOM)), msg).unwrap();
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = create(deps.as_mut(), vault_msg());
+        bet(deps.as_mut(), "alice", vault_id, "yes", 100).unwrap();
+        bet(deps.as_mut(), "bob", vault_id, "no", 100).unwrap();
+        resolve(deps.as_mut(), vault_id, "yes");
+        claim(deps.as_mut(), "alice", vault_id).unwrap();
+        claim(deps.as_mut(), "bob", vault_id).unwrap();
+
+        // 10% of alice's 100 profit, 40% of that to her referrer
+        assert_eq!(balance(deps.as_ref(), "alice"), Uint128::new(190));
+        assert_eq!(balance(deps.as_ref(), "referrer"), Uint128::new(4));
+        assert_eq!(balance(deps.as_ref(), "treasury"), Uint128::new(6));
+        assert_eq!(balance(deps.as_ref(), "bob"), Uint128::zero());
+        assert_eq!(referral(deps.as_ref(), "referrer").earnings, Uint128::new(4));
+    }
+
+    fn event_attrs(res: &Response, name: &str) -> Vec<(String, String)> {
+        let ty = format!("cowbet_{}", name);
+        let event = res.events.iter().find(|event| event.ty == ty).expect("event not emitted");
+        event.attributes.iter().map(|a| (a.key.clone(), a.value.clone())).collect()
+    }
+
+    fn attrs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn handlers_emit_typed_events() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let info = mock_info("alice", &coins(100, DENOM));
+        let res = execute(deps.as_mut(), mock_env(), info, ExecuteMsg::BetTokens { referrer: None }).unwrap();
+        let expected = [("amount", "100"), ("address", "alice"), ("balance", "100")];
+        assert_eq!(event_attrs(&res, "deposit"), attrs(&expected));
+
+        let msg = ExecuteMsg::CreateVault(vault_msg());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let end_height = (mock_env().block.height + DEFAULT_END_HEIGHT_BLOCKS).to_string();
+        let expected = [
+            ("vault_id", "1"),
+            ("creator", "creator"),
+            ("end_height", end_height.as_str()),
+            ("status", "deposits_open"),
+            ("bond", "0"),
+            ("address", "creator"),
+            ("balance", "0"),
+        ];
+        assert_eq!(event_attrs(&res, "create_vault"), attrs(&expected));
+        assert!(res.attributes.iter().any(|a| a.key == "vault_id" && a.value == "1"));
+
+        let res = bet(deps.as_mut(), "alice", 1, "yes", 30).unwrap();
+        let expected = [
+            ("vault_id", "1"),
+            ("outcome", "yes"),
+            ("amount", "30"),
+            ("position", "30"),
+            ("address", "alice"),
+            ("balance", "70"),
+        ];
+        assert_eq!(event_attrs(&res, "bet"), attrs(&expected));
+
+        let mut env = mock_env();
+        env.block.height += DEFAULT_END_HEIGHT_BLOCKS;
+        let msg = ExecuteMsg::EndDeposits { vault_id: 1 };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let expected = [("vault_id", "1"), ("previous_status", "deposits_open"), ("status", "in_progress")];
+        assert_eq!(event_attrs(&res, "end_deposits"), attrs(&expected));
+
+        let msg = ExecuteMsg::ResolveVault { vault_id: 1, outcome: "yes".to_string() };
+        let res = execute(deps.as_mut(), env, mock_info("creator", &[]), msg).unwrap();
+        let expected = [
+            ("vault_id", "1"),
+            ("outcome", "yes"),
+            ("previous_status", "in_progress"),
+            ("status", "finished"),
+            ("on_time", "true"),
+            ("bond_returned", "0"),
+            ("slashed", "0"),
+            ("creator_payout", "0"),
+            ("address", "creator"),
+            ("balance", "0"),
+        ];
+        assert_eq!(event_attrs(&res, "resolve"), attrs(&expected));
+
+        let res = claim(deps.as_mut(), "alice", 1).unwrap();
+        let expected = [("vault_id", "1"), ("payout", "30"), ("address", "alice"), ("balance", "100")];
+        assert_eq!(event_attrs(&res, "claim"), attrs(&expected));
+
+        let msg = ExecuteMsg::WithdrawRewards { amount: Some(Uint128::new(40)) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("alice", &[]), msg).unwrap();
+        let expected = [("amount", "40"), ("address", "alice"), ("balance", "60")];
+        assert_eq!(event_attrs(&res, "withdraw"), attrs(&expected));
+    }
+
+    #[test]
+    fn cancelled_vault_event_reports_transition() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        let vault_id = create(deps.as_mut(), vault_msg());
+        let msg = ExecuteMsg::EndVault { vault_id };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let expected = [
+            ("vault_id", "1"),
+            ("previous_status", "deposits_open"),
+            ("status", "closed"),
+            ("address", "creator"),
+            ("balance", "0"),
+        ];
+        assert_eq!(event_attrs(&res, "end_vault"), attrs(&expected));
+    }
+
+    #[test]
+    fn fund_moving_events_report_balances() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        require_bond(deps.as_mut(), 50);
+        deposit(deps.as_mut(), "creator", 100);
+        deposit(deps.as_mut(), "alice", 100);
+
+        let msg = ExecuteMsg::CreateVault(vault_msg());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(event_attrs(&res, "create_vault")[4..], attrs(&[("bond", "50"), ("address", "creator"), ("balance", "50")]));
+
+        bet(deps.as_mut(), "alice", 1, "yes", 30).unwrap();
+        let res = transfer(deps.as_mut(), "alice", 1, "bob", 10).unwrap();
+        let expected = [
+            ("vault_id", "1"),
+            ("amount", "10"),
+            ("from", "alice"),
+            ("to", "bob"),
+            ("address", "alice"),
+            ("balance", "70"),
+        ];
+        assert_eq!(event_attrs(&res, "transfer_position"), attrs(&expected));
+
+        let res = expire(deps.as_mut(), 1).unwrap();
+        let expected = [
+            ("vault_id", "1"),
+            ("slashed", "50"),
+            ("previous_status", "deposits_open"),
+            ("status", "closed"),
+            ("address", "treasury"),
+            ("balance", "50"),
+        ];
+        assert_eq!(event_attrs(&res, "expire_vault"), attrs(&expected));
+    }
+
+    #[test]
+    fn expire_commitments_event_reports_total() {
+        let mut deps = mock_dependencies(&[]);
+        setup(deps.as_mut());
+        deposit(deps.as_mut(), "alice", 100);
+        deposit(deps.as_mut(), "bob", 100);
+        let vault_id = commit_reveal_vault(deps.as_mut(), false);
+        commit(deps.as_mut(), "alice", vault_id, "yes", "pepper", 30).unwrap();
+        commit(deps.as_mut(), "bob", vault_id, "no", "salt", 20).unwrap();
+
+        let (mut env, reveal_deadline) = end_deposits_env(deps.as_mut(), vault_id);
+        env.block.height = reveal_deadline + 1;
+        let msg = ExecuteMsg::ExpireCommitments { vault_id };
+        let res = execute(deps.as_mut(), env, mock_info("anyone", &[]), msg).unwrap();
+        let expected = [("vault_id", "1"), ("count", "2"), ("amount", "50"), ("refunded", "false")];
+        assert_eq!(event_attrs(&res, "expire_commitments"), attrs(&expected));
     }
 }
-*/
\ No newline at end of file
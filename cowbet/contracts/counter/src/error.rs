@@ -44,6 +44,9 @@ pub enum ContractError {
     #[error("Vault deposit period has not expired (expire_height {expire_height})")]
     VaultDepositPeriodNotExpired { expire_height: u64 },
 
+    #[error("Vault deposit period has expired (expire_height {expire_height})")]
+    VaultDepositPeriodExpired { expire_height: u64 },
+
     #[error("sender has already voted in Vault")]
     VaultSenderVoted {},
 
@@ -62,7 +65,188 @@ pub enum ContractError {
     #[error("sender amount tokens insufficient")]
     VaultInsufficientAmt {},
 
+    #[error("excessive retract amount (max_amount {max_amount})")]
+    ExcessiveRetract { max_amount: Uint128 },
+
+    #[error("early exit fee must be between 0 and 1")]
+    InvalidExitFee {},
+
+    #[error("bet below vault minimum (min_bet {min_bet})")]
+    BetBelowMinimum { min_bet: Uint128 },
+
+    #[error("position above vault maximum per address (max_bet {max_bet})")]
+    BetAboveMaximum { max_bet: Uint128 },
+
+    #[error("Vault pool cap reached (max_pool {max_pool})")]
+    VaultPoolCapReached { max_pool: Uint128 },
+
+    #[error("invalid bet limits (min_bet must be at least 1 and not above max_bet or max_pool)")]
+    InvalidBetLimits {},
+
     #[error("Unauthorized action")]
     Unauthorized {},
 
-}
\ No newline at end of file
+    #[error("sender is not a member of the private Vault")]
+    VaultNotMember {},
+
+    #[error("Vault is not private")]
+    VaultNotPrivate {},
+
+    #[error("Vault only accepts committed bets")]
+    VaultRequiresCommitment {},
+
+    #[error("Vault does not accept committed bets")]
+    VaultNotCommitReveal {},
+
+    #[error("revealed bet does not match commitment")]
+    CommitmentMismatch {},
+
+    #[error("Vault reveal period has expired (reveal_deadline {reveal_deadline})")]
+    VaultRevealPeriodExpired { reveal_deadline: u64 },
+
+    #[error("Vault resolution period has not expired (resolve_deadline {resolve_deadline})")]
+    VaultResolutionPeriodNotExpired { resolve_deadline: u64 },
+
+    #[error("Vault reveal period has not expired (reveal_deadline {reveal_deadline})")]
+    VaultRevealPeriodNotExpired { reveal_deadline: u64 },
+
+    #[error("Vault has unrevealed commitments, expire them first")]
+    VaultHasUnrevealedCommitments {},
+
+    #[error("invalid outcomes (2 to {max_outcomes} distinct, non-empty names)")]
+    InvalidOutcomes { max_outcomes: u64 },
+
+    #[error("operation not supported by this kind of Vault")]
+    VaultKindMismatch {},
+
+    #[error("invalid buckets (3 to {max_boundaries} strictly ascending boundaries)")]
+    InvalidBuckets { max_boundaries: u64 },
+
+    #[error("invalid metadata (at most {max_tags} distinct tags, names up to {max_label_length} bytes, URIs up to {max_uri_length} bytes, a 32 byte rules hash)")]
+    InvalidMetadata {
+        max_tags: u64,
+        max_label_length: u64,
+        max_uri_length: u64,
+    },
+
+    #[error("invalid condition (parent Vault must be unresolved and offer the outcome)")]
+    InvalidCondition {},
+
+    #[error("parent Vault has not resolved on the condition yet")]
+    VaultConditionPending {},
+
+    #[error("scalar lower bound must be below the upper bound")]
+    InvalidScalarBounds {},
+
+    #[error("liquidity parameter must be positive")]
+    InvalidLiquidity {},
+
+    #[error("trade price moved beyond the given limit (amount {amount})")]
+    SlippageExceeded { amount: Uint128 },
+
+    #[error("sender outcome shares insufficient")]
+    InsufficientShares {},
+
+    #[error("swap fee must be below 1")]
+    InvalidSwapFee {},
+
+    #[error("sender liquidity shares insufficient")]
+    InsufficientLpShares {},
+
+    #[error("cash out discount must be between 0 and 1")]
+    InvalidCashOutDiscount {},
+
+    #[error("excessive transfer amount (max_amount {max_amount})")]
+    ExcessiveTransfer { max_amount: Uint128 },
+
+    #[error("cannot transfer a position to its own holder")]
+    InvalidTransferRecipient {},
+
+    #[error("recipient already holds a position on another side")]
+    RecipientVotedOtherSide {},
+
+    #[error("recipient has an unrevealed commitment in the vault")]
+    RecipientHasCommitment {},
+
+    #[error("position receipts are not enabled")]
+    PositionNftNotConfigured {},
+
+    #[error("position is held by receipt {token_id}")]
+    PositionHasReceipt { token_id: String },
+
+    #[error("invalid receipt {token_id}")]
+    InvalidReceipt { token_id: String },
+
+    #[error("sender does not own receipt {token_id}")]
+    NotReceiptOwner { token_id: String },
+
+    #[error("Vault does not allow cashing out")]
+    VaultCashOutDisabled {},
+
+    #[error("cash out reserve insufficient (available {available})")]
+    CashOutReserveInsufficient { available: Uint128 },
+
+    #[error("invalid parlay (2 to {max_legs} legs on distinct vaults)")]
+    InvalidParlay { max_legs: u64 },
+
+    #[error("parlay leg on Vault {vault_id} has no implied probability")]
+    ParlayLegUnpriced { vault_id: u64 },
+
+    #[error("parlay leg on Vault {vault_id} has odds that can be moved and taken back for free")]
+    ParlayLegUnwindable { vault_id: u64 },
+
+    #[error("parlay leg on Vault {vault_id} pays more than {max_odds} times its stake")]
+    ParlayLegOddsTooLong { vault_id: u64, max_odds: u64 },
+
+    #[error("parlay reserve insufficient (available {available})")]
+    ParlayReserveInsufficient { available: Uint128 },
+
+    #[error("Parlay does not exist")]
+    ParlayDoesNotExist {},
+
+    #[error("parlay legs are not all resolved yet")]
+    ParlayNotSettled {},
+
+    #[error("parlay already settled")]
+    ParlaySettled {},
+
+    #[error("Template does not exist")]
+    TemplateDoesNotExist {},
+
+    #[error("Series does not exist")]
+    SeriesDoesNotExist {},
+
+    #[error("a series needs a positive interval and count")]
+    InvalidSeries {},
+
+    #[error("every vault of the series has been spawned")]
+    SeriesComplete {},
+
+    #[error("next series vault is not due yet (spawn_height {spawn_height})")]
+    SeriesVaultNotDue { spawn_height: u64 },
+
+    #[error("protocol fee and referral share must be at most 1")]
+    InvalidProtocolFee {},
+
+    #[error("cannot refer yourself")]
+    InvalidReferrer {},
+
+    #[error("Vault result is not in its dispute period")]
+    VaultNotInDisputePeriod {},
+
+    #[error("Vault result has been disputed already")]
+    VaultDisputed {},
+
+    #[error("Vault result is not disputed")]
+    VaultNotDisputed {},
+
+    #[error("Vault dispute period has expired (dispute_deadline {dispute_deadline})")]
+    VaultDisputePeriodExpired { dispute_deadline: u64 },
+
+    #[error("Vault dispute period has not expired (dispute_deadline {dispute_deadline})")]
+    VaultDisputePeriodNotExpired { dispute_deadline: u64 },
+
+    #[error("Vault result is not final yet")]
+    VaultResultNotFinal {},
+
+}
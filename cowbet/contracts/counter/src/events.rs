@@ -0,0 +1,53 @@
+// Typed events for indexers.
+//
+// Every execute handler emits one event of type `cowbet_<name>`, which the
+// chain reports as `wasm-cowbet_<name>`. Vault events lead with `vault_id`,
+// balance changes end with the account's free `balance` afterwards, and
+// status changes carry both `previous_status` and `status`.
+use cosmwasm_std::{Addr, Event, Uint128};
+
+use crate::state::{ParlayStatus, VaultStatus};
+
+pub const EVENT_PREFIX: &str = "cowbet_";
+
+/// event returns an empty `cowbet_<name>` event
+pub fn event(name: &str) -> Event {
+    Event::new(format!("{}{}", EVENT_PREFIX, name))
+}
+
+/// vault_event returns a `cowbet_<name>` event about a vault
+pub fn vault_event(name: &str, vault_id: u64) -> Event {
+    event(name).add_attribute("vault_id", vault_id.to_string())
+}
+
+/// with_balance adds an account and its free balance after the change
+pub fn with_balance(event: Event, address: &Addr, balance: Uint128) -> Event {
+    event
+        .add_attribute("address", address)
+        .add_attribute("balance", balance)
+}
+
+/// with_status adds a vault's status transition
+pub fn with_status(event: Event, previous: &VaultStatus, status: &VaultStatus) -> Event {
+    event
+        .add_attribute("previous_status", status_name(previous))
+        .add_attribute("status", status_name(status))
+}
+
+pub fn status_name(status: &VaultStatus) -> &'static str {
+    match status {
+        VaultStatus::Closed => "closed",
+        VaultStatus::DepositsOpen => "deposits_open",
+        VaultStatus::InProgress => "in_progress",
+        VaultStatus::Finished => "finished",
+    }
+}
+
+pub fn parlay_status_name(status: &ParlayStatus) -> &'static str {
+    match status {
+        ParlayStatus::Open => "open",
+        ParlayStatus::Won => "won",
+        ParlayStatus::Lost => "lost",
+        ParlayStatus::Void => "void",
+    }
+}
@@ -0,0 +1,461 @@
+// Typed client for a deployed CowBet contract.
+//
+// Builders return the CosmosMsg to add to a Response (or to execute in
+// multi-test); queries decode straight into the response types.
+use cosmwasm_std::{to_binary, Addr, Binary, Coin, CosmosMsg, Decimal, QuerierWrapper, StdResult, Uint128, WasmMsg};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{
+    BettorStatsResponse, CashOutQuoteResponse, CreateTemplateMsg, CreateVaultMsg, ExecuteMsg, LeaderboardResponse,
+    LpSharesResponse, ParlayLegMsg, ParlayResponse, PoolResponse, PositionsResponse, PricesResponse, QueryMsg, ReceiptResponse,
+    ReferralResponse, SharesResponse, SimulateSwapResponse, SimulateTradeResponse, TokenBetResponse, VaultListResponse,
+    VaultMemberResponse, VaultResponse,
+};
+use crate::state::{CreatorStats, LeaderboardMetric, State, VaultSeries, VaultTemplate};
+
+/// CowBetContract wraps the address of a CowBet contract
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CowBetContract(pub Addr);
+
+impl CowBetContract {
+    pub fn addr(&self) -> Addr {
+        self.0.clone()
+    }
+
+    pub fn call<T: Into<ExecuteMsg>>(&self, msg: T) -> StdResult<CosmosMsg> {
+        self.call_with_funds(msg, vec![])
+    }
+
+    pub fn call_with_funds<T: Into<ExecuteMsg>>(&self, msg: T, funds: Vec<Coin>) -> StdResult<CosmosMsg> {
+        let msg = to_binary(&msg.into())?;
+        Ok(WasmMsg::Execute {
+            contract_addr: self.addr().into(),
+            msg,
+            funds,
+        }
+        .into())
+    }
+
+    /// bet_tokens deposits `funds`, which must be in the contract's denom
+    pub fn bet_tokens(&self, funds: Coin, referrer: Option<String>) -> StdResult<CosmosMsg> {
+        self.call_with_funds(ExecuteMsg::BetTokens { referrer }, vec![funds])
+    }
+
+    pub fn withdraw_rewards(&self, amount: Option<Uint128>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::WithdrawRewards { amount })
+    }
+
+    pub fn cast_bet(
+        &self,
+        vault_id: u64,
+        vote: impl Into<String>,
+        weight: Uint128,
+        referrer: Option<String>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CastBet {
+            vault_id,
+            vote: vote.into(),
+            weight,
+            referrer,
+        })
+    }
+
+    /// create_vault takes any creation bond or market funding from the
+    /// sender's deposited balance, so no funds are attached
+    pub fn create_vault(&self, msg: CreateVaultMsg) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CreateVault(msg))
+    }
+
+    pub fn end_deposits(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::EndDeposits { vault_id })
+    }
+
+    pub fn end_vault(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::EndVault { vault_id })
+    }
+
+    pub fn retract_bet(&self, vault_id: u64, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::RetractBet { vault_id, amount })
+    }
+
+    pub fn add_members(&self, vault_id: u64, addresses: Vec<String>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::AddMembers { vault_id, addresses })
+    }
+
+    pub fn remove_members(&self, vault_id: u64, addresses: Vec<String>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::RemoveMembers { vault_id, addresses })
+    }
+
+    /// commit_bet hides a vote behind `commitment`, see contract::bet_commitment
+    pub fn commit_bet(&self, vault_id: u64, commitment: Binary, weight: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CommitBet {
+            vault_id,
+            commitment,
+            weight,
+        })
+    }
+
+    pub fn reveal_bet(&self, vault_id: u64, vote: impl Into<String>, salt: impl Into<String>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::RevealBet {
+            vault_id,
+            vote: vote.into(),
+            salt: salt.into(),
+        })
+    }
+
+    pub fn expire_commitments(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ExpireCommitments { vault_id })
+    }
+
+    pub fn fund_cash_out_reserve(&self, vault_id: u64, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::FundCashOutReserve { vault_id, amount })
+    }
+
+    pub fn cash_out(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CashOut { vault_id })
+    }
+
+    pub fn transfer_position(
+        &self,
+        vault_id: u64,
+        recipient: impl Into<String>,
+        amount: Uint128,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::TransferPosition {
+            vault_id,
+            recipient: recipient.into(),
+            amount,
+        })
+    }
+
+    pub fn mint_receipt(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::MintReceipt { vault_id })
+    }
+
+    pub fn claim_receipt(&self, token_id: impl Into<String>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ClaimReceipt { token_id: token_id.into() })
+    }
+
+    pub fn buy_shares(
+        &self,
+        vault_id: u64,
+        outcome: impl Into<String>,
+        shares: Uint128,
+        max_cost: Option<Uint128>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::BuyShares {
+            vault_id,
+            outcome: outcome.into(),
+            shares,
+            max_cost,
+        })
+    }
+
+    pub fn sell_shares(
+        &self,
+        vault_id: u64,
+        outcome: impl Into<String>,
+        shares: Uint128,
+        min_proceeds: Option<Uint128>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SellShares {
+            vault_id,
+            outcome: outcome.into(),
+            shares,
+            min_proceeds,
+        })
+    }
+
+    pub fn split_collateral(&self, vault_id: u64, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SplitCollateral { vault_id, amount })
+    }
+
+    pub fn merge_outcomes(&self, vault_id: u64, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::MergeOutcomes { vault_id, amount })
+    }
+
+    pub fn swap_outcome(
+        &self,
+        vault_id: u64,
+        offer_outcome: impl Into<String>,
+        amount: Uint128,
+        min_return: Option<Uint128>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SwapOutcome {
+            vault_id,
+            offer_outcome: offer_outcome.into(),
+            amount,
+            min_return,
+        })
+    }
+
+    pub fn provide_liquidity(&self, vault_id: u64, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ProvideLiquidity { vault_id, amount })
+    }
+
+    pub fn withdraw_liquidity(&self, vault_id: u64, shares: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::WithdrawLiquidity { vault_id, shares })
+    }
+
+    pub fn resolve_vault(&self, vault_id: u64, outcome: impl Into<String>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ResolveVault {
+            vault_id,
+            outcome: outcome.into(),
+        })
+    }
+
+    pub fn resolve_with_value(&self, vault_id: u64, value: Decimal) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ResolveWithValue { vault_id, value })
+    }
+
+    pub fn claim_winnings(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ClaimWinnings { vault_id })
+    }
+
+    pub fn expire_vault(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::ExpireVault { vault_id })
+    }
+
+    pub fn dispute_result(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::DisputeResult { vault_id })
+    }
+
+    pub fn settle_dispute(&self, vault_id: u64, outcome: Option<String>, value: Option<Decimal>) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SettleDispute {
+            vault_id,
+            outcome,
+            value,
+        })
+    }
+
+    pub fn finalize_vault(&self, vault_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::FinalizeVault { vault_id })
+    }
+
+    pub fn update_config(
+        &self,
+        creation_bond: Option<Uint128>,
+        resolution_period: Option<u64>,
+        dispute_period: Option<u64>,
+        treasury: Option<String>,
+        protocol_fee: Option<Decimal>,
+        referral_share: Option<Decimal>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::UpdateConfig {
+            creation_bond,
+            resolution_period,
+            dispute_period,
+            treasury,
+            protocol_fee,
+            referral_share,
+        })
+    }
+
+    pub fn fund_parlay_reserve(&self, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::FundParlayReserve { amount })
+    }
+
+    pub fn withdraw_parlay_reserve(&self, amount: Uint128) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::WithdrawParlayReserve { amount })
+    }
+
+    pub fn place_parlay(
+        &self,
+        legs: Vec<ParlayLegMsg>,
+        stake: Uint128,
+        min_payout: Option<Uint128>,
+    ) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::PlaceParlay {
+            legs,
+            stake,
+            min_payout,
+        })
+    }
+
+    pub fn settle_parlay(&self, parlay_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SettleParlay { parlay_id })
+    }
+
+    pub fn create_template(&self, msg: CreateTemplateMsg) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CreateTemplate(msg))
+    }
+
+    pub fn create_series(&self, template_id: u64, interval: u64, count: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::CreateSeries {
+            template_id,
+            interval,
+            count,
+        })
+    }
+
+    pub fn spawn_series_vault(&self, series_id: u64) -> StdResult<CosmosMsg> {
+        self.call(ExecuteMsg::SpawnSeriesVault { series_id })
+    }
+
+    fn query<T: DeserializeOwned>(&self, querier: &QuerierWrapper, msg: QueryMsg) -> StdResult<T> {
+        querier.query_wasm_smart(self.addr(), &msg)
+    }
+
+    pub fn config(&self, querier: &QuerierWrapper) -> StdResult<State> {
+        self.query(querier, QueryMsg::Config {})
+    }
+
+    pub fn token_bet(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<TokenBetResponse> {
+        self.query(querier, QueryMsg::TokenBet { address: address.into() })
+    }
+
+    pub fn positions(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<PositionsResponse> {
+        self.query(querier, QueryMsg::Positions { address: address.into() })
+    }
+
+    pub fn vault(&self, querier: &QuerierWrapper, vault_id: u64) -> StdResult<VaultResponse> {
+        self.query(querier, QueryMsg::Vault { vault_id })
+    }
+
+    pub fn vault_member(
+        &self,
+        querier: &QuerierWrapper,
+        vault_id: u64,
+        address: impl Into<String>,
+    ) -> StdResult<VaultMemberResponse> {
+        self.query(querier, QueryMsg::VaultMember { vault_id, address: address.into() })
+    }
+
+    pub fn prices(&self, querier: &QuerierWrapper, vault_id: u64) -> StdResult<PricesResponse> {
+        self.query(querier, QueryMsg::Prices { vault_id })
+    }
+
+    pub fn simulate_trade(
+        &self,
+        querier: &QuerierWrapper,
+        vault_id: u64,
+        outcome: impl Into<String>,
+        shares: Uint128,
+        sell: bool,
+    ) -> StdResult<SimulateTradeResponse> {
+        let msg = QueryMsg::SimulateTrade {
+            vault_id,
+            outcome: outcome.into(),
+            shares,
+            sell,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn simulate_swap(
+        &self,
+        querier: &QuerierWrapper,
+        vault_id: u64,
+        offer_outcome: impl Into<String>,
+        amount: Uint128,
+    ) -> StdResult<SimulateSwapResponse> {
+        let msg = QueryMsg::SimulateSwap {
+            vault_id,
+            offer_outcome: offer_outcome.into(),
+            amount,
+        };
+        self.query(querier, msg)
+    }
+
+    /// shares returns the outcome units an address holds in a market vault
+    pub fn shares(&self, querier: &QuerierWrapper, vault_id: u64, address: impl Into<String>) -> StdResult<SharesResponse> {
+        self.query(querier, QueryMsg::Shares { vault_id, address: address.into() })
+    }
+
+    pub fn pool(&self, querier: &QuerierWrapper, vault_id: u64) -> StdResult<PoolResponse> {
+        self.query(querier, QueryMsg::Pool { vault_id })
+    }
+
+    pub fn cash_out_quote(
+        &self,
+        querier: &QuerierWrapper,
+        vault_id: u64,
+        address: impl Into<String>,
+    ) -> StdResult<CashOutQuoteResponse> {
+        self.query(querier, QueryMsg::CashOutQuote { vault_id, address: address.into() })
+    }
+
+    pub fn receipt(&self, querier: &QuerierWrapper, vault_id: u64, address: impl Into<String>) -> StdResult<ReceiptResponse> {
+        self.query(querier, QueryMsg::Receipt { vault_id, address: address.into() })
+    }
+
+    pub fn lp_shares(
+        &self,
+        querier: &QuerierWrapper,
+        vault_id: u64,
+        address: impl Into<String>,
+    ) -> StdResult<LpSharesResponse> {
+        self.query(querier, QueryMsg::LpShares { vault_id, address: address.into() })
+    }
+
+    pub fn parlay(&self, querier: &QuerierWrapper, parlay_id: u64) -> StdResult<ParlayResponse> {
+        self.query(querier, QueryMsg::Parlay { parlay_id })
+    }
+
+    pub fn template(&self, querier: &QuerierWrapper, template_id: u64) -> StdResult<VaultTemplate> {
+        self.query(querier, QueryMsg::Template { template_id })
+    }
+
+    pub fn series(&self, querier: &QuerierWrapper, series_id: u64) -> StdResult<VaultSeries> {
+        self.query(querier, QueryMsg::Series { series_id })
+    }
+
+    pub fn vaults_by_category(
+        &self,
+        querier: &QuerierWrapper,
+        category: impl Into<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<VaultListResponse> {
+        let msg = QueryMsg::VaultsByCategory {
+            category: category.into(),
+            start_after,
+            limit,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn vaults_by_tag(
+        &self,
+        querier: &QuerierWrapper,
+        tag: impl Into<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    ) -> StdResult<VaultListResponse> {
+        let msg = QueryMsg::VaultsByTag {
+            tag: tag.into(),
+            start_after,
+            limit,
+        };
+        self.query(querier, msg)
+    }
+
+    pub fn creator_stats(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<CreatorStats> {
+        self.query(querier, QueryMsg::CreatorStats { address: address.into() })
+    }
+
+    pub fn bettor_stats(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<BettorStatsResponse> {
+        self.query(querier, QueryMsg::BettorStats { address: address.into() })
+    }
+
+    pub fn referral(&self, querier: &QuerierWrapper, address: impl Into<String>) -> StdResult<ReferralResponse> {
+        self.query(querier, QueryMsg::Referral { address: address.into() })
+    }
+
+    pub fn leaderboard(
+        &self,
+        querier: &QuerierWrapper,
+        metric: LeaderboardMetric,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<LeaderboardResponse> {
+        let msg = QueryMsg::Leaderboard {
+            metric,
+            start_after,
+            limit,
+        };
+        self.query(querier, msg)
+    }
+}
@@ -1,71 +1,238 @@
 #[cfg(test)]
 mod tests {
-    use crate::helpers::CwTemplateContract;
-    use crate::msg::InstantiateMsg;
-    use cosmwasm_std::{Addr, Coin, Empty, Uint128};
-    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+    use crate::contract::DEFAULT_END_HEIGHT_BLOCKS;
+    use crate::helpers::CowBetContract;
+    use crate::msg::{CreateVaultMsg, CreateVaultResponse, InstantiateMsg};
+    use crate::state::VaultStatus;
+    use crate::ContractError;
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+    use cosmwasm_std::{coin, coins, from_binary, Addr, CosmosMsg, Empty, Event, StdResult, Uint128};
+    use cw_multi_test::{App, AppResponse, BankKeeper, Contract, ContractWrapper, Executor};
 
-    pub fn contract_template() -> Box<dyn Contract<Empty>> {
+    const OWNER: &str = "owner";
+    const CREATOR: &str = "creator";
+    const BETTORS: [&str; 3] = ["alice", "bob", "carol"];
+    const DENOM: &str = "uusd";
+
+    pub fn contract_cowbet() -> Box<dyn Contract<Empty>> {
         let contract = ContractWrapper::new(
             crate::contract::execute,
             crate::contract::instantiate,
             crate::contract::query,
-        );
+        )
+        .with_reply(crate::contract::reply);
         Box::new(contract)
     }
 
-    const USER: &str = "USER";
-    const ADMIN: &str = "ADMIN";
-    const NATIVE_DENOM: &str = "denom";
-
     fn mock_app() -> App {
-        AppBuilder::new().build(|router, _, storage| {
-            router
-                .bank
-                .init_balance(
-                    storage,
-                    &Addr::unchecked(USER),
-                    vec![Coin {
-                        denom: NATIVE_DENOM.to_string(),
-                        amount: Uint128::new(1),
-                    }],
-                )
-                .unwrap();
-        })
-    }
-
-    fn proper_instantiate() -> (App, CwTemplateContract) {
+        let env = mock_env();
+        let mut app = App::new(MockApi::default(), env.block, BankKeeper::new(), MockStorage::new());
+        for bettor in BETTORS {
+            app.init_bank_balance(&Addr::unchecked(bettor), coins(100, DENOM)).unwrap();
+        }
+        app
+    }
+
+    fn proper_instantiate() -> (App, CowBetContract) {
         let mut app = mock_app();
-        let cw_template_id = app.store_code(contract_template());
-
-        let msg = InstantiateMsg { count: 1i32 };
-        let cw_template_contract_addr = app
-            .instantiate_contract(
-                cw_template_id,
-                Addr::unchecked(ADMIN),
-                &msg,
-                &[],
-                "test",
-                None,
-            )
+        let code_id = app.store_code(contract_cowbet());
+        let msg = InstantiateMsg {
+            denom: DENOM.to_string(),
+            position_nft_code_id: None,
+            creation_bond: None,
+            resolution_period: None,
+            treasury: None,
+        };
+        let contract_addr = app
+            .instantiate_contract(code_id, Addr::unchecked(OWNER), &msg, &[], "cowbet", None)
             .unwrap();
+        (app, CowBetContract(contract_addr))
+    }
+
+    fn vault_msg() -> CreateVaultMsg {
+        CreateVaultMsg {
+            description: "will it rain".to_string(),
+            kind: None,
+            outcomes: None,
+            start_height: None,
+            end_height: None,
+            allow_side_switch: None,
+            early_exit_fee: None,
+            min_bet: None,
+            max_bet: None,
+            max_pool: None,
+            allowlist: None,
+            member_group: None,
+            commit_reveal: None,
+            swap_fee: None,
+            cash_out: None,
+            buckets: None,
+            condition: None,
+            metadata: None,
+        }
+    }
+
+    fn execute(app: &mut App, sender: &str, msg: StdResult<CosmosMsg>) -> AppResponse {
+        app.execute(Addr::unchecked(sender), msg.unwrap()).unwrap()
+    }
+
+    fn deposit(app: &mut App, contract: &CowBetContract, sender: &str, amount: u128) {
+        execute(app, sender, contract.bet_tokens(coin(amount, DENOM), None));
+    }
+
+    fn create_vault(app: &mut App, contract: &CowBetContract) -> u64 {
+        let res = execute(app, CREATOR, contract.create_vault(vault_msg()));
+        let data: CreateVaultResponse = from_binary(&res.data.unwrap()).unwrap();
+        data.vault_id
+    }
+
+    fn bet(app: &mut App, contract: &CowBetContract, sender: &str, vault_id: u64, vote: &str, weight: u128) {
+        execute(app, sender, contract.cast_bet(vault_id, vote, Uint128::new(weight), None));
+    }
+
+    fn token_balance(app: &App, contract: &CowBetContract, address: &str) -> Uint128 {
+        contract.token_bet(&app.wrap(), address).unwrap().token_balance
+    }
+
+    fn vault_status(app: &App, contract: &CowBetContract, vault_id: u64) -> VaultStatus {
+        contract.vault(&app.wrap(), vault_id).unwrap().status
+    }
 
-        let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
+    fn bank_balance(app: &App, address: &str) -> Uint128 {
+        app.wrap().query_balance(address, DENOM).unwrap().amount
+    }
 
-        (app, cw_template_contract)
+    fn end_deposits(app: &mut App, contract: &CowBetContract, vault_id: u64) {
+        app.update_block(|block| block.height += DEFAULT_END_HEIGHT_BLOCKS);
+        execute(app, CREATOR, contract.end_deposits(vault_id));
     }
 
-    mod count {
-        use super::*;
-        use crate::msg::ExecuteMsg;
+    #[test]
+    fn full_vault_lifecycle() {
+        let (mut app, contract) = proper_instantiate();
+        deposit(&mut app, &contract, "alice", 100);
+        deposit(&mut app, &contract, "bob", 50);
+        deposit(&mut app, &contract, "carol", 50);
+        assert_eq!(bank_balance(&app, contract.addr().as_str()), Uint128::new(200));
+        assert_eq!(bank_balance(&app, "bob"), Uint128::new(50));
+        assert_eq!(contract.config(&app.wrap()).unwrap().bet_tokens, Uint128::new(200));
+
+        let vault_id = create_vault(&mut app, &contract);
+        bet(&mut app, &contract, "alice", vault_id, "yes", 60);
+        bet(&mut app, &contract, "bob", vault_id, "no", 40);
+        bet(&mut app, &contract, "carol", vault_id, "no", 20);
+        assert_eq!(vault_status(&app, &contract, vault_id), VaultStatus::DepositsOpen);
+
+        end_deposits(&mut app, &contract, vault_id);
+        assert_eq!(vault_status(&app, &contract, vault_id), VaultStatus::InProgress);
+        let res = execute(&mut app, CREATOR, contract.resolve_vault(vault_id, "yes"));
+        res.assert_event(
+            &Event::new("wasm-cowbet_resolve")
+                .add_attribute("vault_id", vault_id.to_string())
+                .add_attribute("outcome", "yes")
+                .add_attribute("status", "finished"),
+        );
+        assert_eq!(vault_status(&app, &contract, vault_id), VaultStatus::Finished);
+
+        // alice takes the whole 120 pot, the others lose their stake
+        for bettor in BETTORS {
+            execute(&mut app, bettor, contract.claim_winnings(vault_id));
+        }
+        assert_eq!(token_balance(&app, &contract, "alice"), Uint128::new(160));
+        assert_eq!(token_balance(&app, &contract, "bob"), Uint128::new(10));
+        assert_eq!(token_balance(&app, &contract, "carol"), Uint128::new(30));
+
+        for bettor in BETTORS {
+            execute(&mut app, bettor, contract.withdraw_rewards(None));
+        }
+        assert_eq!(bank_balance(&app, "alice"), Uint128::new(160));
+        assert_eq!(bank_balance(&app, "bob"), Uint128::new(60));
+        assert_eq!(bank_balance(&app, "carol"), Uint128::new(80));
+        assert_eq!(bank_balance(&app, contract.addr().as_str()), Uint128::zero());
+        assert_eq!(token_balance(&app, &contract, "alice"), Uint128::zero());
+    }
+
+    #[test]
+    fn locked_stake_cannot_be_withdrawn() {
+        let (mut app, contract) = proper_instantiate();
+        deposit(&mut app, &contract, "alice", 100);
+        let vault_id = create_vault(&mut app, &contract);
+        bet(&mut app, &contract, "alice", vault_id, "yes", 70);
+
+        let msg = contract.withdraw_rewards(Some(Uint128::new(50))).unwrap();
+        let err = app.execute(Addr::unchecked("alice"), msg).unwrap_err();
+        match err.downcast::<ContractError>().unwrap() {
+            ContractError::ExcessiveWithdraw { max_amount } => assert_eq!(max_amount, Uint128::new(30)),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(bank_balance(&app, "alice"), Uint128::zero());
+
+        execute(&mut app, "alice", contract.withdraw_rewards(Some(Uint128::new(30))));
+        assert_eq!(bank_balance(&app, "alice"), Uint128::new(30));
+        assert_eq!(bank_balance(&app, contract.addr().as_str()), Uint128::new(70));
+    }
 
-        #[test]
-        fn count() {
-            let (mut app, cw_template_contract) = proper_instantiate();
+    #[test]
+    fn cancelled_vault_refunds_every_stake() {
+        let (mut app, contract) = proper_instantiate();
+        for bettor in BETTORS {
+            deposit(&mut app, &contract, bettor, 100);
+        }
+        let vault_id = create_vault(&mut app, &contract);
+        bet(&mut app, &contract, "alice", vault_id, "yes", 40);
+        bet(&mut app, &contract, "bob", vault_id, "no", 25);
 
-            let msg = ExecuteMsg::Increment {};
-            let cosmos_msg = cw_template_contract.call(msg).unwrap();
-            app.execute(Addr::unchecked(USER), cosmos_msg).unwrap();
+        execute(&mut app, CREATOR, contract.end_vault(vault_id));
+        assert_eq!(vault_status(&app, &contract, vault_id), VaultStatus::Closed);
+        for bettor in ["alice", "bob"] {
+            execute(&mut app, bettor, contract.claim_winnings(vault_id));
+        }
+        for bettor in BETTORS {
+            execute(&mut app, bettor, contract.withdraw_rewards(None));
+            assert_eq!(bank_balance(&app, bettor), Uint128::new(100));
         }
     }
+
+    #[test]
+    fn client_reads_stats_after_settlement() {
+        let (mut app, contract) = proper_instantiate();
+        deposit(&mut app, &contract, "alice", 100);
+        deposit(&mut app, &contract, "bob", 100);
+        let vault_id = create_vault(&mut app, &contract);
+        bet(&mut app, &contract, "alice", vault_id, "yes", 30);
+        bet(&mut app, &contract, "bob", vault_id, "no", 10);
+        end_deposits(&mut app, &contract, vault_id);
+        execute(&mut app, CREATOR, contract.resolve_vault(vault_id, "no"));
+        execute(&mut app, "bob", contract.claim_winnings(vault_id));
+
+        let stats = contract.bettor_stats(&app.wrap(), "bob").unwrap();
+        assert_eq!(stats.total_won, Uint128::new(40));
+        assert_eq!(stats.net_pnl, "30");
+        assert_eq!(contract.creator_stats(&app.wrap(), CREATOR).unwrap().resolved_on_time, 1);
+        let vault = contract.vault(&app.wrap(), vault_id).unwrap();
+        assert_eq!(vault.result, "no");
+    }
+
+    #[test]
+    fn client_lists_open_positions() {
+        let (mut app, contract) = proper_instantiate();
+        deposit(&mut app, &contract, "alice", 100);
+        deposit(&mut app, &contract, "bob", 100);
+        let settled = create_vault(&mut app, &contract);
+        let open = create_vault(&mut app, &contract);
+        bet(&mut app, &contract, "alice", settled, "yes", 30);
+        bet(&mut app, &contract, "bob", settled, "no", 10);
+        bet(&mut app, &contract, "alice", open, "no", 25);
+        end_deposits(&mut app, &contract, settled);
+        execute(&mut app, CREATOR, contract.resolve_vault(settled, "yes"));
+        execute(&mut app, "alice", contract.claim_winnings(settled));
+
+        let positions = contract.positions(&app.wrap(), "alice").unwrap().positions;
+        assert_eq!(positions.len(), 1);
+        assert_eq!((positions[0].vault_id, positions[0].status.clone()), (open, VaultStatus::DepositsOpen));
+        assert_eq!((positions[0].outcome.as_deref(), positions[0].stake), (Some("no"), Uint128::new(25)));
+        let positions = contract.positions(&app.wrap(), "bob").unwrap().positions;
+        assert_eq!(positions[0].vault_id, settled);
+        assert!(contract.positions(&app.wrap(), "carol").unwrap().positions.is_empty());
+    }
 }
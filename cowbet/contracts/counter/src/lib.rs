@@ -1,8 +1,14 @@
+pub mod amm;
 pub mod contract;
 mod error;
+pub mod events;
+pub mod helpers;
 pub mod coin_helpers;
 pub mod integration_tests;
+pub mod lmsr;
 pub mod msg;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod schema;
 pub mod state;
 
 pub use crate::error::ContractError;
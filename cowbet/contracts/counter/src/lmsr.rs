@@ -0,0 +1,195 @@
+// Logarithmic market scoring rule pricing for Lmsr vaults.
+//
+// Fixed-point values are u128 with 18 decimals, the same layout as
+// cosmwasm_std::Decimal, so everything stays integer-only and runs in wasm.
+use cosmwasm_std::{Decimal, StdError, StdResult, Uint128, Uint256};
+use std::convert::TryInto;
+
+const SCALE: u128 = 1_000_000_000_000_000_000;
+const LN_2: u128 = 693_147_180_559_945_309;
+// e^-42 is below 1e-18, so anything further from the leading outcome rounds to zero
+const MAX_EXP_ARG: u128 = 42 * SCALE;
+
+fn mul(a: u128, b: u128) -> u128 {
+    a * b / SCALE
+}
+
+fn div(a: u128, b: u128) -> u128 {
+    a * SCALE / b
+}
+
+/// exp_neg returns e^(-x) for a non-negative fixed-point x
+pub fn exp_neg(x: u128) -> u128 {
+    if x >= MAX_EXP_ARG {
+        return 0;
+    }
+    // e^-x = 2^-k / e^r with x = k * ln(2) + r and 0 <= r < ln(2)
+    let k = x / LN_2;
+    let r = x - k * LN_2;
+    let mut term = SCALE;
+    let mut sum = SCALE;
+    let mut n = 1;
+    while term > 0 {
+        term = mul(term, r) / n;
+        sum += term;
+        n += 1;
+    }
+    div(SCALE, sum) >> k
+}
+
+/// ln returns the natural logarithm of a fixed-point x >= 1
+pub fn ln(x: u128) -> u128 {
+    assert!(x >= SCALE, "ln is only defined here for x >= 1");
+    // ln(x) = k * ln(2) + ln(m) with x = 2^k * m and 1 <= m < 2
+    let mut k = 0;
+    let mut m = x;
+    while m >= 2 * SCALE {
+        m /= 2;
+        k += 1;
+    }
+    // ln(m) = 2 * artanh(z) with z = (m - 1) / (m + 1) < 1/3
+    let z = div(m - SCALE, m + SCALE);
+    let z2 = mul(z, z);
+    let mut term = z;
+    let mut sum = 0;
+    let mut n = 1;
+    while term > 0 {
+        sum += term / n;
+        term = mul(term, z2);
+        n += 2;
+    }
+    k * LN_2 + 2 * sum
+}
+
+/// weights returns exp((q_i - max q) / b) for every outcome, each in (0, 1]
+fn weights(liquidity: Uint128, shares: &[Uint128]) -> Vec<u128> {
+    let max = shares.iter().max().copied().unwrap_or_default();
+    shares
+        .iter()
+        .map(|q| {
+            let gap = max - *q;
+            if gap.u128() / liquidity.u128() >= MAX_EXP_ARG / SCALE {
+                0
+            } else {
+                exp_neg(gap.multiply_ratio(SCALE, liquidity).u128())
+            }
+        })
+        .collect()
+}
+
+/// cost returns C(q) = b * ln(sum(exp(q_i / b))) in collateral units. Callers
+/// pick the rounding so that the market maker never undercharges.
+pub fn cost(liquidity: Uint128, shares: &[Uint128], round_up: bool) -> StdResult<Uint128> {
+    let max = shares.iter().max().copied().unwrap_or_default();
+    let sum: u128 = weights(liquidity, shares).iter().sum();
+    let product = Uint256::from(liquidity.u128()) * Uint256::from(ln(sum));
+    let mut offset = product / Uint256::from(SCALE);
+    if round_up && offset * Uint256::from(SCALE) != product {
+        offset += Uint256::from(1u8);
+    }
+    let offset: Uint128 = offset
+        .try_into()
+        .map_err(|_| StdError::generic_err("lmsr cost overflow"))?;
+    Ok(max.checked_add(offset)?)
+}
+
+/// prices returns the instantaneous price of each outcome, which sum to one
+pub fn prices(liquidity: Uint128, shares: &[Uint128]) -> Vec<Decimal> {
+    let weights = weights(liquidity, shares);
+    let sum: u128 = weights.iter().sum();
+    weights.iter().map(|w| Decimal::from_ratio(*w, sum)).collect()
+}
+
+/// trade_amount returns what buying (or selling) `amount` shares of outcome
+/// `index` costs (or pays), rounded in the market maker's favour. Selling more
+/// than is outstanding or buying past the u128 range is an overflow error.
+pub fn trade_amount(
+    liquidity: Uint128,
+    shares: &[Uint128],
+    index: usize,
+    amount: Uint128,
+    sell: bool,
+) -> StdResult<Uint128> {
+    let mut after = shares.to_vec();
+    if sell {
+        after[index] = after[index].checked_sub(amount)?;
+        Ok(cost(liquidity, shares, false)?.saturating_sub(cost(liquidity, &after, true)?))
+    } else {
+        after[index] = after[index].checked_add(amount)?;
+        Ok(cost(liquidity, &after, true)?.saturating_sub(cost(liquidity, shares, false)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: u128, b: u128, tolerance: u128) -> bool {
+        a.max(b) - a.min(b) <= tolerance
+    }
+
+    #[test]
+    fn exp_neg_matches_known_values() {
+        assert_eq!(exp_neg(0), SCALE);
+        // e^-1 = 0.367879441171442321...
+        assert!(close(exp_neg(SCALE), 367_879_441_171_442_321, 10));
+        // e^-10 = 0.0000453999297624848...
+        assert!(close(exp_neg(10 * SCALE), 45_399_929_762_484, 10));
+        assert_eq!(exp_neg(MAX_EXP_ARG), 0);
+    }
+
+    #[test]
+    fn ln_matches_known_values() {
+        // truncation costs a few units in the last of 18 decimals
+        assert_eq!(ln(SCALE), 0);
+        assert!(close(ln(2 * SCALE), LN_2, 100));
+        // ln(3) = 1.098612288668109691...
+        assert!(close(ln(3 * SCALE), 1_098_612_288_668_109_691, 100));
+        // ln(1000) = 6.907755278982137052...
+        assert!(close(ln(1000 * SCALE), 6_907_755_278_982_137_052, 100));
+    }
+
+    #[test]
+    fn empty_market_costs_b_ln_n() {
+        let b = Uint128::new(1_000_000);
+        let zeros = vec![Uint128::zero(); 2];
+        // 1_000_000 * ln(2) = 693147.18...
+        assert_eq!(cost(b, &zeros, false).unwrap(), Uint128::new(693_147));
+        assert_eq!(cost(b, &zeros, true).unwrap(), Uint128::new(693_148));
+        assert_eq!(prices(b, &zeros), vec![Decimal::percent(50); 2]);
+    }
+
+    #[test]
+    fn buying_moves_price_towards_one() {
+        let b = Uint128::new(1_000_000);
+        let shares = vec![Uint128::new(2_000_000), Uint128::zero(), Uint128::zero()];
+        let p = prices(b, &shares);
+        assert!(p[0] > Decimal::percent(78) && p[0] < Decimal::percent(79));
+        assert!(p[1] == p[2]);
+
+        let buy = trade_amount(b, &shares, 0, Uint128::new(1_000), false).unwrap();
+        let sell = trade_amount(b, &shares, 0, Uint128::new(1_000), true).unwrap();
+        // marginal price ~0.787 per share; the spread favours the market maker
+        assert!(sell < buy);
+        assert!(buy <= Uint128::new(789) && sell >= Uint128::new(785));
+    }
+
+    #[test]
+    fn far_behind_outcome_costs_nothing_extra() {
+        let b = Uint128::new(10);
+        let shares = vec![Uint128::new(1_000_000), Uint128::zero()];
+        assert_eq!(prices(b, &shares)[1], Decimal::zero());
+        assert_eq!(cost(b, &shares, true).unwrap(), Uint128::new(1_000_000));
+    }
+
+    #[test]
+    fn out_of_range_trades_are_errors() {
+        let b = Uint128::new(1_000_000);
+        let shares = vec![Uint128::new(500), Uint128::zero()];
+        assert!(trade_amount(b, &shares, 0, Uint128::MAX, false).is_err());
+        // tied outcomes near the top cost b * ln(2) more than u128 can hold
+        let near_max = Uint128::MAX - Uint128::new(10);
+        assert!(trade_amount(b, &[near_max, Uint128::zero()], 1, near_max, false).is_err());
+        assert!(trade_amount(b, &shares, 0, Uint128::new(501), true).is_err());
+    }
+}
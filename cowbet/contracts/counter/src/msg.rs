@@ -1,36 +1,266 @@
-use crate::state::VaultStatus;
-use cosmwasm_std::{ Uint128, Uint64,};
+use crate::state::{CashOutConfig, CommitRevealConfig, DisputeStatus, LeaderboardMetric, ParlayLeg, ParlayStatus, VaultCondition, VaultKind, VaultMetadata, VaultStatus};
+use cosmwasm_std::{ Binary, Decimal, Uint128, Uint64,};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub denom: String,
+    // cw721-base code to instantiate for position receipts, if any
+    pub position_nft_code_id: Option<u64>,
+    // bond a creator puts up for every vault, defaults to none
+    pub creation_bond: Option<Uint128>,
+    // blocks a creator has to resolve a vault once bets are settled
+    pub resolution_period: Option<u64>,
+    // receives slashed bonds, defaults to the owner
+    pub treasury: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+/// Every message emits one `wasm-cowbet_<name>` event besides its `action`
+/// attribute. Vault events start with `vault_id`; events that move the
+/// sender's funds end with its `address` and free `balance` afterwards, and
+/// `expire_vault` ends with the treasury's. `expire_commitments` reports only
+/// the total `amount` taken from its voters. Status changes carry
+/// `previous_status` and `status`. Names: `deposit`, `withdraw`,
+/// `create_vault`, `end_deposits`, `end_vault`, `bet`, `commit_bet`,
+/// `reveal_bet`, `expire_commitments`, `retract_bet`, `fund_cash_out_reserve`,
+/// `cash_out`, `transfer_position`, `add_members`, `remove_members`,
+/// `buy_shares`, `sell_shares`, `split_collateral`, `merge_outcomes`,
+/// `swap_outcome`, `provide_liquidity`, `withdraw_liquidity`, `resolve`,
+/// `claim`, `expire_vault`, `dispute_result`, `settle_dispute`,
+/// `finalize_vault`, `update_config`, `mint_receipt`, `claim_receipt`,
+/// `fund_parlay_reserve`, `withdraw_parlay_reserve`, `place_parlay`,
+/// `settle_parlay`, `create_template`, `create_series` and
+/// `spawn_series_vault`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
+#[allow(clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     CastBet {
         vault_id: u64,
         vote: String,
         weight: Uint128,
+        referrer: Option<String>,
+    },
+    // referrer is only stored for a sender new to the contract
+    BetTokens {
+        referrer: Option<String>,
     },
-    BetTokens {},
     WithdrawRewards {
         amount: Option<Uint128>,
     },
-    CreateVault {
-        description: String,
-        start_height: Option<u64>,
-        end_height: Option<u64>,
-    },
+    CreateVault(CreateVaultMsg),
     EndDeposits {
         vault_id: u64,
     },
     EndVault {
         vault_id: u64,
     },
+    RetractBet {
+        vault_id: u64,
+        amount: Uint128,
+    },
+    AddMembers {
+        vault_id: u64,
+        addresses: Vec<String>,
+    },
+    RemoveMembers {
+        vault_id: u64,
+        addresses: Vec<String>,
+    },
+    // commitment is sha256("{sender}:{vote}:{salt}"), see contract::bet_commitment
+    CommitBet {
+        vault_id: u64,
+        commitment: Binary,
+        weight: Uint128,
+    },
+    RevealBet {
+        vault_id: u64,
+        vote: String,
+        salt: String,
+    },
+    ExpireCommitments {
+        vault_id: u64,
+    },
+    FundCashOutReserve {
+        vault_id: u64,
+        amount: Uint128,
+    },
+    CashOut {
+        vault_id: u64,
+    },
+    TransferPosition {
+        vault_id: u64,
+        recipient: String,
+        amount: Uint128,
+    },
+    MintReceipt {
+        vault_id: u64,
+    },
+    ClaimReceipt {
+        token_id: String,
+    },
+    BuyShares {
+        vault_id: u64,
+        outcome: String,
+        shares: Uint128,
+        max_cost: Option<Uint128>,
+    },
+    SellShares {
+        vault_id: u64,
+        outcome: String,
+        shares: Uint128,
+        min_proceeds: Option<Uint128>,
+    },
+    SplitCollateral {
+        vault_id: u64,
+        amount: Uint128,
+    },
+    MergeOutcomes {
+        vault_id: u64,
+        amount: Uint128,
+    },
+    SwapOutcome {
+        vault_id: u64,
+        offer_outcome: String,
+        amount: Uint128,
+        min_return: Option<Uint128>,
+    },
+    ProvideLiquidity {
+        vault_id: u64,
+        amount: Uint128,
+    },
+    WithdrawLiquidity {
+        vault_id: u64,
+        shares: Uint128,
+    },
+    ResolveVault {
+        vault_id: u64,
+        outcome: String,
+    },
+    ResolveWithValue {
+        vault_id: u64,
+        value: Decimal,
+    },
+    ClaimWinnings {
+        vault_id: u64,
+    },
+    // anyone may close a vault left unresolved past its resolution period
+    ExpireVault {
+        vault_id: u64,
+    },
+    // a bettor challenges a result within the vault's dispute period
+    DisputeResult {
+        vault_id: u64,
+    },
+    // the owner rules on a disputed result, naming what it should have been;
+    // anything else than the result overturns it and slashes the bond
+    SettleDispute {
+        vault_id: u64,
+        outcome: Option<String>,
+        value: Option<Decimal>,
+    },
+    // anyone makes a result final once its dispute period has passed
+    FinalizeVault {
+        vault_id: u64,
+    },
+    UpdateConfig {
+        creation_bond: Option<Uint128>,
+        resolution_period: Option<u64>,
+        // blocks a result can be disputed in, none by default
+        dispute_period: Option<u64>,
+        treasury: Option<String>,
+        protocol_fee: Option<Decimal>,
+        referral_share: Option<Decimal>,
+    },
+    FundParlayReserve {
+        amount: Uint128,
+    },
+    WithdrawParlayReserve {
+        amount: Uint128,
+    },
+    PlaceParlay {
+        legs: Vec<ParlayLegMsg>,
+        stake: Uint128,
+        min_payout: Option<Uint128>,
+    },
+    SettleParlay {
+        parlay_id: u64,
+    },
+    CreateTemplate(CreateTemplateMsg),
+    // spawns the series' first vault right away
+    CreateSeries {
+        template_id: u64,
+        interval: u64,
+        count: u64,
+    },
+    // anyone may spawn the next vault once the last one's deposits have ended
+    SpawnSeriesVault {
+        series_id: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ParlayLegMsg {
+    pub vault_id: u64,
+    pub outcome: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreateVaultMsg {
+    pub description: String,
+    // defaults to a parimutuel pool
+    pub kind: Option<VaultKind>,
+    // defaults to ["yes", "no"], or ["long", "short"] for a Scalar vault
+    pub outcomes: Option<Vec<String>>,
+    pub start_height: Option<u64>,
+    pub end_height: Option<u64>,
+    pub allow_side_switch: Option<bool>,
+    // fraction of a retracted bet kept in the pool, defaults to none
+    pub early_exit_fee: Option<Decimal>,
+    pub min_bet: Option<Uint128>,
+    // largest position a single address may hold
+    pub max_bet: Option<Uint128>,
+    // largest total of all positions in the vault
+    pub max_pool: Option<Uint128>,
+    // setting either of these makes the vault private to its members
+    pub allowlist: Option<Vec<String>>,
+    pub member_group: Option<String>, // cw4 group contract
+    // hide bets behind commitments that are revealed after EndDeposits
+    pub commit_reveal: Option<CommitRevealConfig>,
+    // fraction of every Amm swap kept for liquidity providers, defaults to none
+    pub swap_fee: Option<Decimal>,
+    // let parimutuel bettors sell their position back before resolution
+    pub cash_out: Option<CashOutConfig>,
+    // ascending boundaries of contiguous numeric ranges, used as the outcomes
+    // instead of names, e.g. [0, 10, 20] for "0-10" and "10-20"
+    pub buckets: Option<Vec<Decimal>>,
+    // only keep a pooled vault if another vault resolves on the given
+    // outcome, otherwise cancel it and refund every stake
+    pub condition: Option<VaultCondition>,
+    // category, tags and references for listing the vault
+    pub metadata: Option<VaultMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreateTemplateMsg {
+    // "{n}" is replaced by each vault's position in its series
+    pub description: String,
+    // only pooled kinds, defaults to a parimutuel pool
+    pub kind: Option<VaultKind>,
+    pub outcomes: Option<Vec<String>>,
+    pub buckets: Option<Vec<Decimal>>,
+    // blocks each vault takes deposits for, defaults to DEFAULT_END_HEIGHT_BLOCKS
+    pub duration: Option<u64>,
+    pub allow_side_switch: Option<bool>,
+    pub early_exit_fee: Option<Decimal>,
+    pub min_bet: Option<Uint128>,
+    pub max_bet: Option<Uint128>,
+    pub max_pool: Option<Uint128>,
+    pub metadata: Option<VaultMetadata>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -38,16 +268,190 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     Config {},
     TokenBet { address: String },
+    // unclaimed positions, in the order the address entered their vaults
+    Positions { address: String },
     Vault { vault_id: u64 },
+    VaultMember { vault_id: u64, address: String },
+    Prices { vault_id: u64 },
+    SimulateTrade {
+        vault_id: u64,
+        outcome: String,
+        shares: Uint128,
+        sell: bool,
+    },
+    Shares { vault_id: u64, address: String },
+    SimulateSwap {
+        vault_id: u64,
+        offer_outcome: String,
+        amount: Uint128,
+    },
+    Pool { vault_id: u64 },
+    CashOutQuote { vault_id: u64, address: String },
+    Receipt { vault_id: u64, address: String },
+    LpShares { vault_id: u64, address: String },
+    Parlay { parlay_id: u64 },
+    Template { template_id: u64 },
+    Series { series_id: u64 },
+    // vault ids in ascending order, paginated
+    VaultsByCategory {
+        category: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    VaultsByTag {
+        tag: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    CreatorStats { address: String },
+    BettorStats { address: String },
+    Referral { address: String },
+    // best first; start_after is the address the previous page ended on
+    Leaderboard {
+        metric: LeaderboardMetric,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct VaultResponse {
     pub creator: String,
     pub status: VaultStatus,
+    pub kind: VaultKind,
+    pub outcomes: Vec<String>,
+    pub result: String,
     pub end_height: Option<u64>,
     pub start_height: Option<u64>,
     pub description: String,
+    pub allow_side_switch: bool,
+    pub early_exit_fee: Decimal,
+    pub exit_fees: Uint128,
+    pub min_bet: Uint128,
+    pub max_bet: Option<Uint128>,
+    pub max_pool: Option<Uint128>,
+    pub private: bool,
+    pub member_group: Option<String>,
+    pub commit_reveal: Option<CommitRevealConfig>,
+    pub committed_votes: Uint128,
+    pub reveal_deadline: Option<u64>,
+    pub forfeited: Uint128,
+    pub cash_out: Option<CashOutConfig>,
+    pub cash_out_reserve: Uint128,
+    pub reserve_positions: Vec<(String, Uint128)>,
+    pub resolved_value: Option<Decimal>,
+    pub buckets: Option<Vec<Decimal>>,
+    pub condition: Option<VaultCondition>,
+    pub conditional_vaults: Vec<u64>,
+    pub series_id: Option<u64>,
+    pub metadata: Option<VaultMetadata>,
+    pub bond: Uint128,
+    pub resolution_period: u64,
+    pub resolved_height: Option<u64>,
+    pub dispute_period: u64,
+    pub dispute: Option<DisputeStatus>,
+    pub final_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BettorStatsResponse {
+    pub address: String,
+    pub total_wagered: Uint128,
+    pub total_won: Uint128,
+    pub net_pnl: String, // total_won - total_wagered, may be negative
+    pub wins: u64,
+    pub vaults_participated: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ReferralResponse {
+    pub referrer: Option<String>,
+    pub earnings: Uint128, // protocol fees credited for the address' referrals
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LeaderboardResponse {
+    pub entries: Vec<BettorStatsResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct VaultListResponse {
+    pub vault_ids: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ParlayResponse {
+    pub owner: String,
+    pub legs: Vec<ParlayLeg>,
+    pub stake: Uint128,
+    pub payout: Uint128,
+    pub status: ParlayStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct ReceiptResponse {
+    pub token_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CashOutQuoteResponse {
+    pub position: Uint128,
+    pub value: Uint128, // the position's share of the pot at current odds
+    pub offer: Uint128, // value less the vault's discount
+    pub cash_out_reserve: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct OutcomePrice {
+    pub outcome: String,
+    pub price: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PricesResponse {
+    pub prices: Vec<OutcomePrice>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SimulateTradeResponse {
+    pub amount: Uint128, // cost of a buy or proceeds of a sell
+    pub prices: Vec<OutcomePrice>, // prices after the trade
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SimulateSwapResponse {
+    pub return_amount: Uint128,
+    pub prices: Vec<OutcomePrice>, // prices after the swap
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct OutcomeShares {
+    pub outcome: String,
+    pub shares: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct SharesResponse {
+    pub shares: Vec<OutcomeShares>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PoolResponse {
+    pub reserves: Vec<OutcomeShares>,
+    pub collateral: Uint128,
+    pub swap_fee: Decimal,
+    pub total_lp_shares: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct LpSharesResponse {
+    pub shares: Uint128,
+    pub total_lp_shares: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct VaultMemberResponse {
+    pub member: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
@@ -55,6 +459,21 @@ pub struct CreateVaultResponse {
     pub vault_id: u64,
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CreateTemplateResponse {
+    pub template_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct CreateSeriesResponse {
+    pub series_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PlaceParlayResponse {
+    pub parlay_id: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct VaultCountResponse {
     pub vault_count: u64,
@@ -63,4 +482,19 @@ pub struct VaultCountResponse {
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 pub struct TokenBetResponse {
     pub token_balance: Uint128,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PositionsResponse {
+    pub positions: Vec<PositionResponse>,
+}
+
+// A stake in a pooled vault, or the outcome units held in an Lmsr or Amm vault
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PositionResponse {
+    pub vault_id: u64,
+    pub status: VaultStatus,
+    pub outcome: Option<String>, // side of a pooled stake, none while it is committed
+    pub stake: Uint128,
+    pub shares: Vec<OutcomeShares>,
+}
@@ -0,0 +1,49 @@
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+use crate::msg::{
+    BettorStatsResponse, CashOutQuoteResponse, CreateSeriesResponse, CreateTemplateResponse,
+    CreateVaultResponse, ExecuteMsg, InstantiateMsg, LeaderboardResponse, LpSharesResponse, MigrateMsg,
+    ParlayResponse, PlaceParlayResponse, PoolResponse, PositionsResponse, PricesResponse, QueryMsg, ReceiptResponse,
+    ReferralResponse, SharesResponse, SimulateSwapResponse, SimulateTradeResponse, TokenBetResponse,
+    VaultListResponse, VaultMemberResponse, VaultResponse,
+};
+use crate::state::{CreatorStats, State, Vault, VaultSeries, VaultTemplate};
+
+/// schemas returns the schema of every message, stored type and response the
+/// contract exposes. examples/schema.rs writes them to schema/ and
+/// tests/schema.rs checks the files there against them.
+pub fn schemas() -> Vec<RootSchema> {
+    vec![
+        schema_for!(InstantiateMsg),
+        schema_for!(ExecuteMsg),
+        schema_for!(QueryMsg),
+        schema_for!(MigrateMsg),
+        schema_for!(State),
+        schema_for!(Vault),
+        schema_for!(VaultTemplate),
+        schema_for!(VaultSeries),
+        schema_for!(CreatorStats),
+        schema_for!(TokenBetResponse),
+        schema_for!(PositionsResponse),
+        schema_for!(VaultResponse),
+        schema_for!(VaultMemberResponse),
+        schema_for!(VaultListResponse),
+        schema_for!(PricesResponse),
+        schema_for!(SimulateTradeResponse),
+        schema_for!(SimulateSwapResponse),
+        schema_for!(SharesResponse),
+        schema_for!(PoolResponse),
+        schema_for!(LpSharesResponse),
+        schema_for!(CashOutQuoteResponse),
+        schema_for!(ReceiptResponse),
+        schema_for!(ParlayResponse),
+        schema_for!(BettorStatsResponse),
+        schema_for!(ReferralResponse),
+        schema_for!(LeaderboardResponse),
+        schema_for!(CreateVaultResponse),
+        schema_for!(CreateTemplateResponse),
+        schema_for!(CreateSeriesResponse),
+        schema_for!(PlaceParlayResponse),
+    ]
+}
@@ -1,5 +1,5 @@
 
-use cosmwasm_std::{ Addr, Uint128, Storage, Uint64,}; //u64?
+use cosmwasm_std::{ Addr, Binary, Decimal, Uint128, Storage, Uint64,}; //u64?
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 //use percentage::Percentage;
@@ -12,6 +12,23 @@ use cosmwasm_storage::{
 static CONFIG_KEY: &[u8] = b"config";
 static VAULT_KEY: &[u8] = b"vault";
 static BANK_KEY: &[u8] = b"bank";
+static MEMBERS_KEY: &[u8] = b"members";
+static COMMITMENTS_KEY: &[u8] = b"commitments";
+static LMSR_KEY: &[u8] = b"lmsr";
+static SHARES_KEY: &[u8] = b"shares";
+static AMM_KEY: &[u8] = b"amm";
+static LP_KEY: &[u8] = b"lp";
+static RECEIPTS_KEY: &[u8] = b"receipts";
+static PARLAY_KEY: &[u8] = b"parlay";
+static TEMPLATE_KEY: &[u8] = b"template";
+static SERIES_KEY: &[u8] = b"series";
+static CATEGORY_INDEX_KEY: &[u8] = b"category_index";
+static TAG_INDEX_KEY: &[u8] = b"tag_index";
+static CREATOR_STATS_KEY: &[u8] = b"creator_stats";
+static BETTOR_STATS_KEY: &[u8] = b"bettor_stats";
+static LEADERBOARD_KEY: &[u8] = b"leaderboard";
+static REFERRER_KEY: &[u8] = b"referrer";
+static REFERRAL_EARNINGS_KEY: &[u8] = b"referral_earnings";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
@@ -19,6 +36,17 @@ pub struct State {
     pub owner: Addr,
     pub vault_count: u64,
     pub bet_tokens: Uint128,
+    pub position_nft: Option<Addr>, // cw721 contract minting position receipts
+    pub parlay_count: u64,
+    pub parlay_reserve: Uint128,    // house funds backing what open parlays may win
+    pub template_count: u64,
+    pub series_count: u64,
+    pub creation_bond: Uint128,  // taken from a creator's balance for every new vault
+    pub resolution_period: u64,  // blocks a creator has to resolve once bets are settled
+    pub dispute_period: u64,     // blocks a result can be disputed in before it is paid out on
+    pub treasury: Addr,          // receives slashed bonds and protocol fees
+    pub protocol_fee: Decimal,   // taken from the profit of every settled pooled position
+    pub referral_share: Decimal, // part of a winner's protocol fee credited to their referrer
 }
 
 pub const STATE: Item<State> = Item::new("state");
@@ -33,11 +61,117 @@ pub struct TokenManager {
     pub participated_vaults: Vec<u64>,       // vault_id
 }
 
+// Track record of a vault creator.
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreatorStats {
+    pub vaults_created: u64,
+    pub resolved_on_time: u64, // within the vault's resolution period
+    pub resolved_late: u64,
+    pub cancelled: u64,        // by the creator while deposits were open
+    pub disputed: u64,         // results a bettor disputed
+    pub overturned: u64,       // disputed results the owner overturned
+    pub expired: u64,          // closed by anyone after the resolution period
+}
+
+// Lifetime results of an address. Stakes count as they are placed, payouts
+// as positions are retracted, sold, cashed out or settled.
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BettorStats {
+    pub total_wagered: Uint128, // bets, commitments, share purchases, splits and parlay stakes
+    pub total_won: Uint128,   // everything paid out, stakes returned included
+    pub wins: u64,            // settlements paying more than their stake
+    pub vaults_participated: u64, // settled positions, cash outs and parlays
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardMetric {
+    TotalWagered,
+    TotalWon,
+    Wins,
+    NetPnl,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Voter {
     pub vote: String, //yes or no
     pub weight: Uint128,
-    pub pool_pct: f64, //how much you vault relative to everyone 
+    pub pool_pct: Decimal, //how much you vault relative to everyone
+}
+
+// A hidden bet: the sha256 of (voter, vote, salt) plus the stake behind it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Commitment {
+    pub commitment: Binary,
+    pub weight: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CommitRevealConfig {
+    pub reveal_period: u64,      // blocks after EndDeposits during which bets can be revealed
+    pub refund_unrevealed: bool, // refund unrevealed stakes instead of forfeiting them to the pool
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CashOutConfig {
+    pub discount: Decimal,             // taken off a position's implied value when cashing out
+    pub exit_fees_fund_reserve: bool,  // early exit fees top up the reserve instead of the pot
+}
+
+// Descriptive details for listing a vault, none of which affect betting.
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultMetadata {
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub resolution_source: Option<String>, // URL the creator resolves from
+    pub rules_hash: Option<Binary>,        // sha256 of the long-form rules text
+    pub image_uri: Option<String>,
+}
+
+// A vault only stands if its parent resolves on `outcome`, and is cancelled
+// otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultCondition {
+    pub vault_id: u64,
+    pub outcome: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultKind {
+    Parimutuel,
+    // priced by a logarithmic market scoring rule, liquidity is its b parameter
+    Lmsr { liquidity: Uint128 },
+    // binary outcome units traded against a constant-product pool, liquidity seeds the pool
+    Amm { liquidity: Uint128 },
+    // long and short stakes split the pot linearly by where the resolved value
+    // falls between the bounds
+    Scalar { lower: Decimal, upper: Decimal },
+}
+
+impl VaultKind {
+    /// is_pooled returns whether bettors stake into a shared pot, as opposed
+    /// to trading outcome shares with a market maker
+    pub fn is_pooled(&self) -> bool {
+        matches!(self, VaultKind::Parimutuel | VaultKind::Scalar { .. })
+    }
+}
+
+// Market maker state of an Lmsr vault.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LmsrMarket {
+    pub shares: Vec<Uint128>, // outstanding shares per outcome, in Vault.outcomes order
+    pub collateral: Uint128,  // creator subsidy plus net trading proceeds
+}
+
+// Pool of an Amm vault. Every unit of collateral backs one unit of each
+// outcome, held either by the pool or by traders.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AmmPool {
+    pub reserves: Vec<Uint128>, // pool balance per outcome, in Vault.outcomes order
+    pub collateral: Uint128,    // outstanding complete sets of outcome units
+    pub swap_fee: Decimal,      // share of every swap left in the reserves for liquidity providers
+    pub total_lp_shares: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -48,10 +182,72 @@ pub enum VaultStatus {
     Finished,
 }
 
+// Settings shared by every vault spawned from a template. `{n}` in the
+// description is replaced by the vault's position in its series.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultTemplate {
+    pub creator: Addr,
+    pub description: String,
+    pub kind: VaultKind,
+    pub outcomes: Option<Vec<String>>,
+    pub buckets: Option<Vec<Decimal>>,
+    pub duration: u64, // blocks each vault takes deposits for
+    pub allow_side_switch: bool,
+    pub early_exit_fee: Decimal,
+    pub min_bet: Option<Uint128>,
+    pub max_bet: Option<Uint128>,
+    pub max_pool: Option<Uint128>,
+    pub metadata: Option<VaultMetadata>,
+}
+
+// Vaults spawned from one template on a fixed schedule.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VaultSeries {
+    pub template_id: u64,
+    pub interval: u64, // blocks between the deposit deadlines of consecutive vaults
+    pub count: u64,    // vaults in the whole series
+    pub vault_ids: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ParlayLeg {
+    pub vault_id: u64,
+    pub outcome: String,
+    pub probability: Decimal, // implied probability of the outcome when the parlay was placed
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum ParlayStatus {
+    Open,
+    Won,
+    Lost,
+    Void, // every leg was cancelled or refunded, the stake went back
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum DisputeStatus {
+    Open, // waiting for the owner to rule on it
+    Upheld,
+    Overturned,
+}
+
+// One stake on a combination of outcomes across vaults, paid only if every
+// leg wins. The house reserve covers what it may win beyond the stake.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Parlay {
+    pub owner: Addr,
+    pub legs: Vec<ParlayLeg>,
+    pub stake: Uint128,
+    pub payout: Uint128, // what the parlay pays if every leg wins
+    pub status: ParlayStatus,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Vault {
     pub creator: Addr,
     pub status: VaultStatus,
+    pub kind: VaultKind,
+    pub outcomes: Vec<String>,
     pub yes_votes: Uint128,
     pub no_votes: Uint128,
     pub sum_votes: Uint128,
@@ -62,6 +258,33 @@ pub struct Vault {
     pub description: String,
     pub farmRewards: Uint128,
     pub result: String,
+    pub allow_side_switch: bool, // may a bettor move their position to another side
+    pub early_exit_fee: Decimal,  // fraction of a retracted bet that stays in the pool
+    pub exit_fees: Uint128,       // early exit fees collected so far
+    pub min_bet: Uint128,
+    pub max_bet: Option<Uint128>,  // per address
+    pub max_pool: Option<Uint128>, // sum of all positions
+    pub private: bool,             // only members may bet
+    pub member_group: Option<Addr>, // cw4 group whose members may bet
+    pub commit_reveal: Option<CommitRevealConfig>, // bets are hidden until after deposits
+    pub committed_votes: Uint128,  // stake behind commitments not yet revealed
+    pub reveal_deadline: Option<u64>,
+    pub forfeited: Uint128,        // unrevealed stakes kept in the pool
+    pub cash_out: Option<CashOutConfig>, // bettors may sell positions back to the vault
+    pub cash_out_reserve: Uint128,       // unspent funds for buying positions back
+    pub reserve_positions: Vec<(String, Uint128)>, // positions bought back, by outcome
+    pub resolved_value: Option<Decimal>, // what a Scalar or bucketed vault settled on
+    pub buckets: Option<Vec<Decimal>>,   // boundaries of the numeric ranges named by outcomes
+    pub condition: Option<VaultCondition>, // parent outcome this vault depends on
+    pub conditional_vaults: Vec<u64>,      // vaults depending on this one's outcome
+    pub series_id: Option<u64>,            // series the vault was spawned in
+    pub metadata: Option<VaultMetadata>,
+    pub bond: Uint128,            // creation bond held until the vault's result is final
+    pub resolution_period: u64,   // blocks the creator has to resolve once bets are settled
+    pub resolved_height: Option<u64>, // block the result was set in
+    pub dispute_period: u64,      // blocks the result can be disputed in
+    pub dispute: Option<DisputeStatus>,
+    pub final_height: Option<u64>, // block the result became final in, positions are paid out from then on
 }
 
 
@@ -87,4 +310,147 @@ pub fn bank(storage: &mut dyn Storage) -> Bucket<TokenManager> {
 
 pub fn bank_read(storage: &dyn Storage) -> ReadonlyBucket<TokenManager> {
     bucket_read(storage, BANK_KEY)
-}
\ No newline at end of file
+}
+
+pub fn vault_members(storage: &mut dyn Storage, vault_id: u64) -> Bucket<'_, bool> {
+    Bucket::multilevel(storage, &[MEMBERS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn vault_members_read(storage: &dyn Storage, vault_id: u64) -> ReadonlyBucket<'_, bool> {
+    ReadonlyBucket::multilevel(storage, &[MEMBERS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn commitments(storage: &mut dyn Storage, vault_id: u64) -> Bucket<'_, Commitment> {
+    Bucket::multilevel(storage, &[COMMITMENTS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn commitments_read(storage: &dyn Storage, vault_id: u64) -> ReadonlyBucket<'_, Commitment> {
+    ReadonlyBucket::multilevel(storage, &[COMMITMENTS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn lmsr_market(storage: &mut dyn Storage) -> Bucket<'_, LmsrMarket> {
+    bucket(storage, LMSR_KEY)
+}
+
+pub fn lmsr_market_read(storage: &dyn Storage) -> ReadonlyBucket<'_, LmsrMarket> {
+    bucket_read(storage, LMSR_KEY)
+}
+
+pub fn shares(storage: &mut dyn Storage, vault_id: u64) -> Bucket<'_, Vec<Uint128>> {
+    Bucket::multilevel(storage, &[SHARES_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn shares_read(storage: &dyn Storage, vault_id: u64) -> ReadonlyBucket<'_, Vec<Uint128>> {
+    ReadonlyBucket::multilevel(storage, &[SHARES_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn amm_pool(storage: &mut dyn Storage) -> Bucket<'_, AmmPool> {
+    bucket(storage, AMM_KEY)
+}
+
+pub fn amm_pool_read(storage: &dyn Storage) -> ReadonlyBucket<'_, AmmPool> {
+    bucket_read(storage, AMM_KEY)
+}
+
+// liquidity provider shares of an Amm vault's pool, keyed by address
+pub fn lp_shares(storage: &mut dyn Storage, vault_id: u64) -> Bucket<'_, Uint128> {
+    Bucket::multilevel(storage, &[LP_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn lp_shares_read(storage: &dyn Storage, vault_id: u64) -> ReadonlyBucket<'_, Uint128> {
+    ReadonlyBucket::multilevel(storage, &[LP_KEY, &vault_id.to_be_bytes()])
+}
+
+// cw721 token ids of receipted positions, keyed by the bettor's address
+pub fn receipts(storage: &mut dyn Storage, vault_id: u64) -> Bucket<'_, String> {
+    Bucket::multilevel(storage, &[RECEIPTS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn receipts_read(storage: &dyn Storage, vault_id: u64) -> ReadonlyBucket<'_, String> {
+    ReadonlyBucket::multilevel(storage, &[RECEIPTS_KEY, &vault_id.to_be_bytes()])
+}
+
+pub fn parlay(storage: &mut dyn Storage) -> Bucket<'_, Parlay> {
+    bucket(storage, PARLAY_KEY)
+}
+
+pub fn parlay_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Parlay> {
+    bucket_read(storage, PARLAY_KEY)
+}
+
+pub fn template(storage: &mut dyn Storage) -> Bucket<'_, VaultTemplate> {
+    bucket(storage, TEMPLATE_KEY)
+}
+
+pub fn template_read(storage: &dyn Storage) -> ReadonlyBucket<'_, VaultTemplate> {
+    bucket_read(storage, TEMPLATE_KEY)
+}
+
+pub fn series(storage: &mut dyn Storage) -> Bucket<'_, VaultSeries> {
+    bucket(storage, SERIES_KEY)
+}
+
+pub fn series_read(storage: &dyn Storage) -> ReadonlyBucket<'_, VaultSeries> {
+    bucket_read(storage, SERIES_KEY)
+}
+
+// ids of the vaults in a category, keyed by vault id
+pub fn category_index<'a>(storage: &'a mut dyn Storage, category: &str) -> Bucket<'a, bool> {
+    Bucket::multilevel(storage, &[CATEGORY_INDEX_KEY, category.as_bytes()])
+}
+
+pub fn category_index_read<'a>(storage: &'a dyn Storage, category: &str) -> ReadonlyBucket<'a, bool> {
+    ReadonlyBucket::multilevel(storage, &[CATEGORY_INDEX_KEY, category.as_bytes()])
+}
+
+// ids of the vaults carrying a tag, keyed by vault id
+pub fn tag_index<'a>(storage: &'a mut dyn Storage, tag: &str) -> Bucket<'a, bool> {
+    Bucket::multilevel(storage, &[TAG_INDEX_KEY, tag.as_bytes()])
+}
+
+pub fn tag_index_read<'a>(storage: &'a dyn Storage, tag: &str) -> ReadonlyBucket<'a, bool> {
+    ReadonlyBucket::multilevel(storage, &[TAG_INDEX_KEY, tag.as_bytes()])
+}
+
+pub fn creator_stats(storage: &mut dyn Storage) -> Bucket<'_, CreatorStats> {
+    bucket(storage, CREATOR_STATS_KEY)
+}
+
+pub fn creator_stats_read(storage: &dyn Storage) -> ReadonlyBucket<'_, CreatorStats> {
+    bucket_read(storage, CREATOR_STATS_KEY)
+}
+
+pub fn bettor_stats(storage: &mut dyn Storage) -> Bucket<'_, BettorStats> {
+    bucket(storage, BETTOR_STATS_KEY)
+}
+
+pub fn bettor_stats_read(storage: &dyn Storage) -> ReadonlyBucket<'_, BettorStats> {
+    bucket_read(storage, BETTOR_STATS_KEY)
+}
+
+// addresses ranked by a metric, keyed by the big-endian score followed by the address
+pub fn leaderboard<'a>(storage: &'a mut dyn Storage, metric: &str) -> Bucket<'a, bool> {
+    Bucket::multilevel(storage, &[LEADERBOARD_KEY, metric.as_bytes()])
+}
+
+pub fn leaderboard_read<'a>(storage: &'a dyn Storage, metric: &str) -> ReadonlyBucket<'a, bool> {
+    ReadonlyBucket::multilevel(storage, &[LEADERBOARD_KEY, metric.as_bytes()])
+}
+
+// who referred an address, set the first time it names a referrer
+pub fn referrer(storage: &mut dyn Storage) -> Bucket<'_, Addr> {
+    bucket(storage, REFERRER_KEY)
+}
+
+pub fn referrer_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Addr> {
+    bucket_read(storage, REFERRER_KEY)
+}
+
+// protocol fees credited to a referrer so far
+pub fn referral_earnings(storage: &mut dyn Storage) -> Bucket<'_, Uint128> {
+    bucket(storage, REFERRAL_EARNINGS_KEY)
+}
+
+pub fn referral_earnings_read(storage: &dyn Storage) -> ReadonlyBucket<'_, Uint128> {
+    bucket_read(storage, REFERRAL_EARNINGS_KEY)
+}
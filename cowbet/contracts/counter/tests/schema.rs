@@ -0,0 +1,52 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use schemars::schema::RootSchema;
+use serde_json::Value;
+
+use counter::schema::schemas;
+
+fn schema_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schema")
+}
+
+// matches the file names export_schema writes
+fn file_name(schema: &RootSchema) -> String {
+    let title = schema.schema.metadata.as_ref().and_then(|m| m.title.clone()).unwrap();
+    let mut name = String::new();
+    for (i, c) in title.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+    name + ".json"
+}
+
+#[test]
+fn checked_in_schema_matches_types() {
+    for schema in schemas() {
+        let path = schema_dir().join(file_name(&schema));
+        let raw = fs::read_to_string(&path)
+            .unwrap_or_else(|_| panic!("{} is missing, run `cargo schema`", path.display()));
+        let checked_in: Value = serde_json::from_str(&raw).unwrap();
+        let generated = serde_json::to_value(&schema).unwrap();
+        assert!(
+            checked_in == generated,
+            "{} is out of date, run `cargo schema`",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn schema_dir_has_no_stale_files() {
+    let expected: BTreeSet<String> = schemas().iter().map(file_name).collect();
+    let found: BTreeSet<String> = fs::read_dir(schema_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".json"))
+        .collect();
+    assert_eq!(found, expected, "schema/ is out of date, run `cargo schema`");
+}